@@ -0,0 +1,649 @@
+//! Client-side builders for the instructions that move pool tokens.
+//!
+//! Each builder reads the pool (or the program config, for the pool inits) through
+//! `fetch_account_data_fn`, derives the PDAs and ATAs itself and, when the pool's
+//! mint has a Token-2022 TransferHook extension, resolves the hook's
+//! ExtraAccountMetaList and appends the accounts the transfer needs. The fetch function
+//! has the same shape as the one `spl_token_2022::offchain` takes, e.g.
+//! `|address| client.get_account(&address).map_ok(|opt| opt.map(|acc| acc.data))`.
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    instruction::transfer_checked,
+    offchain::{resolve_extra_transfer_account_metas, AccountDataResult, AccountFetchError},
+    state::Mint,
+};
+use std::future::Future;
+
+use crate::{
+    instruction::{FlashLoanInstruction, ACCOUNTS_PER_LEG},
+    math::withdrawal_for_shares,
+    pda::{find_config_address, find_lp_mint_address, find_pool_address, find_vault_address},
+    state::{Pool as PoolState, ProgramConfig},
+};
+use borsh::BorshDeserialize;
+
+/// source, mint, destination and authority come first in `transfer_checked`.
+const TRANSFER_CHECKED_ACCOUNTS: usize = 4;
+
+/// Builds `InitPool` for the pool the config's counter will hand out next. The
+/// initial deposit comes from `user`'s associated token account for `mint`.
+pub async fn init_pool<F, Fut>(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    initial_amount: u64,
+    fees_bps: u16,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (config, pool) = next_pool_address(program_id, &fetch_account_data_fn).await?;
+    let (vault, _) = find_vault_address(program_id, &pool);
+    let (lp_mint, _) = find_lp_mint_address(program_id, &pool);
+    let user_token_account = get_associated_token_address_with_program_id(user, mint, token_program);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(config, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(user_token_account, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(lp_mint, false),
+            AccountMeta::new(get_associated_token_address_with_program_id(user, &lp_mint, &spl_token_2022::id()), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(*token_program, false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: borsh::to_vec(&FlashLoanInstruction::InitPool {
+            initial_amount,
+            fees_bps,
+            mint: *mint,
+        })?,
+    };
+    // LP mints are Token-2022, which needs passing separately when the pooled mint isn't
+    if token_program != &spl_token_2022::id() {
+        instruction.accounts.push(AccountMeta::new_readonly(spl_token_2022::id(), false));
+    }
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        token_program,
+        &user_token_account,
+        mint,
+        &vault,
+        user,
+        initial_amount,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `InitNativePool` for the SOL pool the config's counter will hand out next.
+pub async fn init_native_pool<F, Fut>(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    initial_amount: u64,
+    fees_bps: u16,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (config, pool) = next_pool_address(program_id, &fetch_account_data_fn).await?;
+    let (vault, _) = find_vault_address(program_id, &pool);
+    let (lp_mint, _) = find_lp_mint_address(program_id, &pool);
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(config, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(lp_mint, false),
+            AccountMeta::new(get_associated_token_address_with_program_id(user, &lp_mint, &spl_token_2022::id()), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token_2022::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: borsh::to_vec(&FlashLoanInstruction::InitNativePool {
+            initial_amount,
+            fees_bps,
+        })?,
+    })
+}
+
+/// Builds `LiquidatePool`, depositing from `user`'s associated token account, or from
+/// `user` itself for a lamport pool.
+pub async fn liquidate_pool<F, Fut>(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    pool_id: u64,
+    token_amount: u64,
+    min_lp_out: u64,
+    deadline: Option<i64>,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let user_ata = user_token_account(user, &pool_data);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: lp_accounts(program_id, user, &user_ata, &pool, &pool_data),
+        data: borsh::to_vec(&FlashLoanInstruction::LiquidatePool {
+            pool_id,
+            token_amount,
+            min_lp_out,
+            deadline,
+        })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &user_ata,
+        &pool_data.token_mint,
+        &pool_data.vault,
+        user,
+        token_amount,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `DeLiquidatePool`, paying out to `user`'s associated token account (or `user`
+/// for a lamport pool). The hook accounts are resolved for the payout the pool's
+/// current share price gives.
+pub async fn deliquidate_pool<F, Fut>(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    pool_id: u64,
+    lp_amount: u64,
+    min_tokens_out: u64,
+    deadline: Option<i64>,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let user_ata = user_token_account(user, &pool_data);
+    let tokens_out = withdrawal_for_shares(lp_amount, pool_data.total_liquidity, pool_data.total_lp_supply)?;
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: lp_accounts(program_id, user, &user_ata, &pool, &pool_data),
+        data: borsh::to_vec(&FlashLoanInstruction::DeLiquidatePool {
+            pool_id,
+            lp_amount,
+            min_tokens_out,
+            deadline,
+        })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &pool_data.vault,
+        &pool_data.token_mint,
+        &user_ata,
+        &pool,
+        tokens_out,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `Borrow` into `borrower`'s associated token account, or `borrower` itself for a
+/// lamport pool.
+pub async fn borrow<F, Fut>(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    pool_id: u64,
+    amount: u64,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let borrower_ata = user_token_account(borrower, &pool_data);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: loan_accounts(program_id, borrower, &pool, &pool_data.vault, &borrower_ata, &pool_data),
+        data: borsh::to_vec(&FlashLoanInstruction::Borrow {
+            pool_id,
+            amount,
+            mint: pool_data.token_mint,
+        })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &pool_data.vault,
+        &pool_data.token_mint,
+        &borrower_ata,
+        &pool,
+        amount,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `Repay` from `borrower`'s associated token account (or `borrower` for a lamport
+/// pool). `amount` is what the matching Borrow owes, transfer fee included.
+pub async fn repay<F, Fut>(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    pool_id: u64,
+    amount: u64,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let borrower_ata = user_token_account(borrower, &pool_data);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: loan_accounts(program_id, borrower, &pool, &borrower_ata, &pool_data.vault, &pool_data),
+        data: borsh::to_vec(&FlashLoanInstruction::Repay { pool_id, amount })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &borrower_ata,
+        &pool_data.token_mint,
+        &pool_data.vault,
+        borrower,
+        amount,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `FlashLoan` into `borrower`'s associated token account (or `borrower` for a
+/// lamport pool). `receiver_accounts` are forwarded to the receiver ahead of the loan's
+/// transfer hook accounts.
+pub async fn flash_loan<F, Fut>(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    pool_id: u64,
+    amount: u64,
+    receiver_program: &Pubkey,
+    receiver_accounts: &[AccountMeta],
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let borrower_ata = user_token_account(borrower, &pool_data);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*borrower, true),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(pool_data.vault, false),
+        AccountMeta::new(borrower_ata, false),
+        AccountMeta::new_readonly(pool_data.token_mint, false),
+        AccountMeta::new_readonly(*receiver_program, false),
+        AccountMeta::new_readonly(pool_data.token_program, false),
+    ];
+    accounts.extend_from_slice(receiver_accounts);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts,
+        data: borsh::to_vec(&FlashLoanInstruction::FlashLoan { pool_id, amount })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &pool_data.vault,
+        &pool_data.token_mint,
+        &borrower_ata,
+        &pool,
+        amount,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Builds `BorrowMany` over `loans`, each a (pool id, amount) pair, into `borrower`'s
+/// associated token accounts (or `borrower` itself for lamport pools). The transfer hook
+/// accounts of every leg follow the legs.
+pub async fn borrow_many<F, Fut>(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    loans: &[(u64, u64)],
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let mut legs = Vec::with_capacity(loans.len());
+    for (pool_id, amount) in loans {
+        let (pool, pool_data) = fetch_pool(program_id, *pool_id, &fetch_account_data_fn).await?;
+        let borrower_ata = user_token_account(borrower, &pool_data);
+        legs.push((pool, pool_data, borrower_ata, *amount));
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*borrower, true),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    for (pool, pool_data, borrower_ata, _) in &legs {
+        accounts.extend(batch_leg_accounts(pool, &pool_data.vault, borrower_ata, pool_data));
+    }
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts,
+        data: borsh::to_vec(&FlashLoanInstruction::BorrowMany {
+            amounts: loans.iter().map(|(_, amount)| *amount).collect(),
+        })?,
+    };
+    for (pool, pool_data, borrower_ata, amount) in &legs {
+        add_transfer_hook_accounts(
+            &mut instruction,
+            &fetch_account_data_fn,
+            &pool_data.token_program,
+            &pool_data.vault,
+            &pool_data.token_mint,
+            borrower_ata,
+            pool,
+            *amount,
+        )
+        .await?;
+    }
+    Ok(instruction)
+}
+
+/// Builds `RepayMany` over `repayments`, each a (pool id, amount) pair, from `borrower`'s
+/// associated token accounts (or `borrower` for lamport pools). Each amount is what the
+/// pool's BorrowMany leg owes, transfer fee included.
+pub async fn repay_many<F, Fut>(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    repayments: &[(u64, u64)],
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let mut legs = Vec::with_capacity(repayments.len());
+    for (pool_id, amount) in repayments {
+        let (pool, pool_data) = fetch_pool(program_id, *pool_id, &fetch_account_data_fn).await?;
+        let borrower_ata = user_token_account(borrower, &pool_data);
+        legs.push((pool, pool_data, borrower_ata, *amount));
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*borrower, true),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    for (pool, pool_data, borrower_ata, _) in &legs {
+        accounts.extend(batch_leg_accounts(pool, borrower_ata, &pool_data.vault, pool_data));
+    }
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts,
+        data: borsh::to_vec(&FlashLoanInstruction::RepayMany {
+            amounts: repayments.iter().map(|(_, amount)| *amount).collect(),
+        })?,
+    };
+    for (_, pool_data, borrower_ata, amount) in &legs {
+        add_transfer_hook_accounts(
+            &mut instruction,
+            &fetch_account_data_fn,
+            &pool_data.token_program,
+            borrower_ata,
+            &pool_data.token_mint,
+            &pool_data.vault,
+            borrower,
+            *amount,
+        )
+        .await?;
+    }
+    Ok(instruction)
+}
+
+/// Builds `CollectProtocolFees`, sending the pool's accrued protocol fees to the config's
+/// protocol fee recipient: its associated token account, or the recipient itself for a
+/// lamport pool.
+pub async fn collect_protocol_fees<F, Fut>(
+    program_id: &Pubkey,
+    pool_id: u64,
+    fetch_account_data_fn: F,
+) -> Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (config, config_data) = fetch_config(program_id, &fetch_account_data_fn).await?;
+    let (pool, pool_data) = fetch_pool(program_id, pool_id, &fetch_account_data_fn).await?;
+    let treasury = user_token_account(&config_data.protocol_fee_recipient, &pool_data);
+
+    let mut instruction = Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(config, false),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(pool_data.vault, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new_readonly(pool_data.token_mint, false),
+            AccountMeta::new_readonly(pool_data.token_program, false),
+        ],
+        data: borsh::to_vec(&FlashLoanInstruction::CollectProtocolFees { pool_id })?,
+    };
+    add_transfer_hook_accounts(
+        &mut instruction,
+        &fetch_account_data_fn,
+        &pool_data.token_program,
+        &pool_data.vault,
+        &pool_data.token_mint,
+        &treasury,
+        &pool,
+        pool_data.accrued_protocol_fees,
+    )
+    .await?;
+    Ok(instruction)
+}
+
+/// Resolves the accounts the mint's transfer hook needs for a `transfer_checked` of
+/// `amount` from `source` to `destination`, and appends the ones `instruction` doesn't
+/// list yet. Adds nothing for mints without a TransferHook extension.
+#[allow(clippy::too_many_arguments)]
+pub async fn add_transfer_hook_accounts<F, Fut>(
+    instruction: &mut Instruction,
+    fetch_account_data_fn: F,
+    token_program: &Pubkey,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+) -> Result<(), AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    // Lamport pools move SOL with the System program, there is no hook to run
+    if token_program == &system_program::id() {
+        return Ok(());
+    }
+    let mint_data = fetch_account_data_fn(*mint)
+        .await?
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let decimals = StateWithExtensions::<Mint>::unpack(&mint_data)?.base.decimals;
+
+    // Resolve against the same transfer the program will make, seeds can point into it
+    let mut transfer_ix = transfer_checked(token_program, source, mint, destination, authority, &[], amount, decimals)?;
+    resolve_extra_transfer_account_metas(&mut transfer_ix, &fetch_account_data_fn, mint).await?;
+
+    for meta in transfer_ix.accounts.into_iter().skip(TRANSFER_CHECKED_ACCOUNTS) {
+        match instruction.accounts.iter_mut().find(|existing| existing.pubkey == meta.pubkey) {
+            Some(existing) => existing.is_writable |= meta.is_writable,
+            None => instruction.accounts.push(meta),
+        }
+    }
+    Ok(())
+}
+
+/// The config and the pool its counter will hand out next.
+async fn next_pool_address<F, Fut>(
+    program_id: &Pubkey,
+    fetch_account_data_fn: &F,
+) -> Result<(Pubkey, Pubkey), AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (config, config_data) = fetch_config(program_id, fetch_account_data_fn).await?;
+    Ok((config, find_pool_address(program_id, config_data.next_pool_id).0))
+}
+
+async fn fetch_config<F, Fut>(
+    program_id: &Pubkey,
+    fetch_account_data_fn: &F,
+) -> Result<(Pubkey, ProgramConfig), AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (config, _) = find_config_address(program_id);
+    let config_data = fetch_account_data_fn(config)
+        .await?
+        .ok_or(ProgramError::UninitializedAccount)?;
+    Ok((config, ProgramConfig::try_from_slice(&config_data)?))
+}
+
+async fn fetch_pool<F, Fut>(
+    program_id: &Pubkey,
+    pool_id: u64,
+    fetch_account_data_fn: &F,
+) -> Result<(Pubkey, PoolState), AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let (pool, _) = find_pool_address(program_id, pool_id);
+    let pool_data = fetch_account_data_fn(pool)
+        .await?
+        .ok_or(ProgramError::UninitializedAccount)?;
+    Ok((pool, PoolState::try_from_slice(&pool_data)?))
+}
+
+/// Fixed accounts of `LiquidatePool` and `DeLiquidatePool`.
+fn lp_accounts(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    user_ata: &Pubkey,
+    pool: &Pubkey,
+    pool_data: &PoolState,
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new(*user_ata, false),
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(pool_data.vault, false),
+        AccountMeta::new_readonly(pool_data.token_mint, false),
+        AccountMeta::new(pool_data.lp_mint, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(user, &pool_data.lp_mint, &pool_data.lp_token_program()),
+            false,
+        ),
+        AccountMeta::new_readonly(pool_data.lp_token_program(), false),
+    ];
+    // The token program slot is the LP's; pools on another program (SPL Token, or System
+    // for a lamport pool) pass theirs after it
+    if pool_data.token_program != pool_data.lp_token_program() {
+        accounts.push(AccountMeta::new_readonly(pool_data.token_program, false));
+    }
+    accounts
+}
+
+/// Where `owner`'s side of a transfer lives: their ATA for the pool's mint, or the owner
+/// itself for a lamport pool.
+fn user_token_account(owner: &Pubkey, pool_data: &PoolState) -> Pubkey {
+    if pool_data.is_native() {
+        *owner
+    } else {
+        get_associated_token_address_with_program_id(owner, &pool_data.token_mint, &pool_data.token_program)
+    }
+}
+
+/// Accounts of `Borrow` and `Repay`, which only differ in the transfer's direction.
+fn loan_accounts(
+    program_id: &Pubkey,
+    borrower: &Pubkey,
+    pool: &Pubkey,
+    first_token_account: &Pubkey,
+    second_token_account: &Pubkey,
+    pool_data: &PoolState,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*borrower, true),
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(*first_token_account, false),
+        AccountMeta::new(*second_token_account, false),
+        AccountMeta::new_readonly(pool_data.token_mint, false),
+        AccountMeta::new_readonly(pool_data.token_program, false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ]
+}
+
+/// One leg of `BorrowMany` or `RepayMany`, which only differ in the transfer's direction.
+fn batch_leg_accounts(
+    pool: &Pubkey,
+    first_token_account: &Pubkey,
+    second_token_account: &Pubkey,
+    pool_data: &PoolState,
+) -> [AccountMeta; ACCOUNTS_PER_LEG] {
+    [
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(*first_token_account, false),
+        AccountMeta::new(*second_token_account, false),
+        AccountMeta::new_readonly(pool_data.token_mint, false),
+        AccountMeta::new_readonly(pool_data.token_program, false),
+    ]
+}
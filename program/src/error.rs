@@ -64,7 +64,76 @@ pub enum FlashLoanError {
     MissingRequiredSignature,
 
     #[error("Invalid instruction data provided")]
-    InvalidInstructionData
+    InvalidInstructionData,
+
+    #[error("There is no open loan on this pool to repay")]
+    NoActiveLoan,
+
+    #[error("The pool has an outstanding flash loan")]
+    LoanOutstanding,
+
+    #[error("Borrow, BorrowMany and Repay must be top-level instructions, programs borrow through FlashLoan")]
+    LoanModeMismatch,
+
+    #[error("More than one later instruction repays the same loan")]
+    MultipleRepayInstructions,
+
+    #[error("The Repay instruction does not name the borrowed pool account")]
+    RepayPoolMismatch,
+
+    #[error("The Repay instruction does not name the pool's vault")]
+    RepayVaultMismatch,
+
+    #[error("The Repay instruction does not mark the vault as writable")]
+    RepayVaultNotWritable,
+
+    #[error("The pool status does not allow this operation")]
+    PoolPaused,
+
+    #[error("Loan amount is above the pool's cap")]
+    LoanCapExceeded,
+
+    #[error("Deposit would take the pool above its cap")]
+    DepositCapExceeded,
+
+    #[error("The pool authority was renounced, its parameters are immutable")]
+    AuthorityRenounced,
+
+    #[error("The protocol is paused")]
+    ProtocolPaused,
+
+    #[error("Protocol fee share can't be above 10000 bps")]
+    InvalidProtocolFeeShare,
+
+    #[error("There are no protocol fees to collect")]
+    NoProtocolFees,
+
+    #[error("Fee is above the maximum allowed")]
+    FeeTooHigh,
+
+    #[error("Fee is not one of the supported fee tiers")]
+    InvalidFeeTier,
+
+    #[error("Initial deposit must be above the locked minimum liquidity")]
+    InitialLiquidityTooLow,
+
+    #[error("Deposit would mint fewer LP tokens than min_lp_out")]
+    LpOutputTooLow,
+
+    #[error("Withdrawal would pay out fewer tokens than min_tokens_out")]
+    TokenOutputTooLow,
+
+    #[error("Transaction landed after its deadline")]
+    DeadlineExceeded,
+
+    #[error("Token program is not SPL Token or Token-2022, or not the pool's")]
+    InvalidTokenProgram,
+
+    #[error("Token mint has an extension the pool cannot account for")]
+    UnsupportedMintExtension,
+
+    #[error("Account does not match the expected PDA")]
+    InvalidPda,
 }
 
 // -----------------------------
@@ -0,0 +1,113 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::FlashLoanError,
+    pda::check_pool_address,
+    state::Pool as PoolState,
+};
+use borsh::{BorshSerialize, BorshDeserialize};
+
+/// Step one of an authority handoff: the current authority names its successor.
+pub fn propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let pool = next_account_info(account_info_iter)?;        // pool state PDA
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+
+    pool_data.check_authority(authority.key)?;
+
+    // Giving up control goes through RenounceAuthority, not a proposal nobody can accept
+    if new_authority == Pubkey::default() {
+        return Err(FlashLoanError::InvalidAuthority.into());
+    }
+
+    pool_data.pending_authority = new_authority;
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+/// Step two of an authority handoff: the proposed key signs to take over.
+pub fn accept_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let new_authority = next_account_info(account_info_iter)?;   // signer
+    let pool = next_account_info(account_info_iter)?;            // pool state PDA
+
+    if !new_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+
+    if pool_data.pending_authority == Pubkey::default() || new_authority.key != &pool_data.pending_authority {
+        return Err(FlashLoanError::InvalidAuthority.into());
+    }
+
+    pool_data.authority = pool_data.pending_authority;
+    pool_data.pending_authority = Pubkey::default();
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+/// Drops the authority for good, freezing the pool's parameters.
+pub fn renounce_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let pool = next_account_info(account_info_iter)?;        // pool state PDA
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+
+    pool_data.check_authority(authority.key)?;
+
+    pool_data.authority = Pubkey::default();
+    pool_data.pending_authority = Pubkey::default();
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
@@ -3,26 +3,37 @@ use solana_program::{
     entrypoint::ProgramResult,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::{instructions::{load_current_index_checked, load_instruction_at_checked}, Sysvar},
+    instruction::AccountMeta,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
 };
-use spl_token::state::Account as TokenAccount;
 
 use crate::{
     error::FlashLoanError,
-    instruction::FlashLoanInstruction,
+    instruction::{FlashLoanInstruction, ACCOUNTS_PER_LEG},
+    pda::{check_pool_address, check_vault_address, pool_signer_seeds},
     state::Pool as PoolState,
-    utils::transfer_tokens,
+    utils::{gross_up_transfer_fee, invoked_via_cpi, transfer_from_vault, unpack_token_account, vault_balance},
 };
 use borsh::{BorshSerialize, BorshDeserialize};
-use spl_token::solana_program::program_pack::Pack;
+
+/// Accounts taking part in one pool's loan, shared by the single and batched
+/// borrow/repay paths.
+pub struct LoanAccounts<'a, 'b> {
+    pub borrower: &'b AccountInfo<'a>,
+    pub pool: &'b AccountInfo<'a>,
+    pub vault: &'b AccountInfo<'a>,
+    pub borrower_token_account: &'b AccountInfo<'a>,
+    pub mint: &'b AccountInfo<'a>,
+    pub token_program: &'b AccountInfo<'a>,
+    pub transfer_hook_accounts: &'b [AccountInfo<'a>],
+}
 
 pub fn borrow(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    pool_id: u8,
+    pool_id: u64,
     amount: u64,
     mint: &Pubkey,
-    bump: u8, // <-- Add bump as parameter
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -31,20 +42,64 @@ pub fn borrow(
     let pool = next_account_info(account_info_iter)?;                     // pool state PDA
     let vault = next_account_info(account_info_iter)?;                    // pool vault (source)
     let borrower_token_account = next_account_info(account_info_iter)?;  // recipient ATA (destination)
+    let token_mint = next_account_info(account_info_iter)?;              // pool's token mint
     let token_program = next_account_info(account_info_iter)?;           // token program
     let instructions_sysvar_account = next_account_info(account_info_iter)?; // instruction sysvar
-    let clock_sysvar = next_account_info(account_info_iter)?; // clock sysvar
+    let transfer_hook_accounts = account_info_iter.as_slice();           // extra accounts for the mint's transfer hook
 
-    let clock = solana_program::sysvar::clock::Clock::from_account_info(clock_sysvar)?;
-    let current_time = clock.unix_timestamp as u8;
+    // Introspection can't see whether a calling program repays before it returns, so
+    // programs borrowing through CPI have to use the callback-style FlashLoan instead
+    if invoked_via_cpi() {
+        return Err(FlashLoanError::LoanModeMismatch.into());
+    }
 
-    //The pool id is the open_time of the pool, so we check if the current time is greater than the pool id
-    if current_time > pool_id {
+    let pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_id != pool_data.pool_id {
         return Err(FlashLoanError::InvalidPoolId.into());
     }
 
+    if *mint != pool_data.token_mint {
+        return Err(FlashLoanError::InvalidTokenMint.into());
+    }
+
+    // === Introspection requires exactly one later Repay for this pool ===
+    let current_ix_index = load_current_index_checked(instructions_sysvar_account)?;
+
+    let loan = LoanAccounts {
+        borrower,
+        pool,
+        vault,
+        borrower_token_account,
+        mint: token_mint,
+        token_program,
+        transfer_hook_accounts,
+    };
+    let amount_owed = open_loan(program_id, &loan, amount, current_ix_index)?;
+
+    find_repay(
+        program_id,
+        instructions_sysvar_account,
+        current_ix_index,
+        pool_id,
+        pool.key,
+        &pool_data.vault,
+        amount_owed,
+    )?;
+
+    Ok(())
+}
+
+/// Validates one pool, snapshots its vault, opens the loan and sends `amount`
+/// to the borrower. Returns what the borrower owes back to this pool, grossed up
+/// for the mint's transfer fee so the vault is whole once it arrives.
+pub fn open_loan(
+    program_id: &Pubkey,
+    loan: &LoanAccounts,
+    amount: u64,
+    ix_index: u16,
+) -> Result<u64, ProgramError> {
     // === Signer check ===
-    if !borrower.is_signer {
+    if !loan.borrower.is_signer {
         return Err(FlashLoanError::MissingRequiredSignature.into());
     }
 
@@ -52,70 +107,655 @@ pub fn borrow(
         return Err(FlashLoanError::InvalidAmount.into());
     }
 
-    let pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
-    if pool_id != pool_data.pool_id {
-        return Err(FlashLoanError::InvalidPoolId.into());
+    let mut pool_data = PoolState::try_from_slice(&loan.pool.try_borrow_data()?)?;
+    check_pool_address(program_id, loan.pool.key, &pool_data)?;
+    check_vault_address(program_id, loan.pool.key, loan.vault.key, &pool_data)?;
+    pool_data.check_token_accounts(loan.mint.key, loan.token_program.key)?;
+
+    if pool_data.loan_active {
+        return Err(FlashLoanError::FlashLoanAlreadyTaken.into());
+    }
+
+    if !pool_data.status.can_borrow() {
+        return Err(FlashLoanError::PoolPaused.into());
     }
 
-    // === Check if ATA exists and is valid ===
-    if borrower_token_account.lamports() == 0 {
+    if pool_data.max_loan_amount != 0 && amount > pool_data.max_loan_amount {
+        return Err(FlashLoanError::LoanCapExceeded.into());
+    }
+
+    // === Check the destination: the borrower's ATA, or the borrower itself for SOL ===
+    if pool_data.is_native() {
+        if loan.borrower_token_account.key != loan.borrower.key {
+            return Err(FlashLoanError::InvalidAccountData.into());
+        }
+    } else if loan.borrower_token_account.lamports() == 0 {
         return Err(FlashLoanError::InvalidAccountData.into()); // ATA must be pre-created externally
     } else {
-        let ata_data = TokenAccount::unpack(&borrower_token_account.try_borrow_data()?)?;
-        if ata_data.owner != *borrower.key || ata_data.mint != *mint {
+        let ata_data = unpack_token_account(loan.borrower_token_account)?;
+        if ata_data.owner != *loan.borrower.key || ata_data.mint != pool_data.token_mint {
             return Err(FlashLoanError::InvalidAccountData.into());
         }
     }
 
     // === Fee calculation ===
-    let fee = amount
-        .checked_mul(pool_data.fees_bps as u64)
-        .ok_or(FlashLoanError::MathError)?
-        .checked_div(10_000)
-        .ok_or(FlashLoanError::MathError)?;
+    let fee = pool_data.calculate_fee(amount)?;
 
-    let borrow_amount = amount
-        .checked_add(fee)
-        .ok_or(FlashLoanError::MathError)?;
+    // === The repayment is grossed up so the vault nets amount + fee after any transfer fee ===
+    let amount_owed = gross_up_transfer_fee(
+        loan.mint,
+        amount.checked_add(fee).ok_or(FlashLoanError::MathError)?,
+    )?;
 
     // === Vault balance check (ensure it has enough tokens) ===
-    // Optional: Load vault token account and check amount, similar to ATA
+    let vault_amount = vault_balance(loan.vault, &pool_data.token_program)?;
+    if vault_amount < amount {
+        return Err(FlashLoanError::InsufficientLiquidity.into());
+    }
 
-    // === Instruction introspection to enforce repay next ===
-    let current_ix_index = load_current_index_checked(instructions_sysvar_account)?;
-    let repay_ix = load_instruction_at_checked(current_ix_index as usize + 1, instructions_sysvar_account)?;
+    // === Open the loan and snapshot the vault so Repay can check it was made whole ===
+    pool_data.loan_active = true;
+    pool_data.loan_borrower = *loan.borrower.key;
+    pool_data.loan_vault_snapshot = vault_amount;
+    pool_data.loan_principal = amount;
+    pool_data.loan_fee = fee;
+    pool_data.loan_ix_index = ix_index;
+    pool_data.serialize(&mut &mut loan.pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
 
-    if repay_ix.program_id != *program_id {
-        return Err(FlashLoanError::InvalidInstructionData.into());
-    }
+    // === Transfer from the vault to the borrower ===
+    let pool_id = pool_data.pool_id.to_le_bytes();
+    let signer_seeds = pool_signer_seeds(&pool_id, &pool_data.bump);
 
-    let repay_ix_data = FlashLoanInstruction::try_from_slice(&repay_ix.data)
-        .map_err(|_| FlashLoanError::InvalidInstructionData)?;
+    transfer_from_vault(
+        loan.vault.clone(),
+        loan.mint.clone(),
+        loan.borrower_token_account.clone(),
+        loan.pool.clone(), // vault authority (PDA)
+        loan.token_program.clone(),
+        loan.transfer_hook_accounts,
+        amount,
+        &signer_seeds,
+    )?;
+
+    Ok(amount_owed)
+}
+
+/// Scans every instruction after `loan_ix_index`, the Borrow's index recorded on the
+/// pool by `open_loan`, for the one that repays `pool`.
+///
+/// Exactly one later Repay (or RepayMany leg) of this program may target the pool. Its
+/// account list has to name the pool, name `vault` (the pool's stored `Pool::vault`) as
+/// writable, and its data has to return `amount_owed`. Any other borrow against the pool
+/// before that repay is rejected, and the scan stops at the next borrow against it after
+/// that repay. Returns the index of the repaying instruction.
+pub fn find_repay(
+    program_id: &Pubkey,
+    instructions_sysvar_account: &AccountInfo,
+    loan_ix_index: u16,
+    pool_id: u64,
+    pool: &Pubkey,
+    vault: &Pubkey,
+    amount_owed: u64,
+) -> Result<usize, ProgramError> {
+    let mut repay_index = None;
+    let mut index = loan_ix_index as usize + 1;
+
+    while let Ok(ix) = load_instruction_at_checked(index, instructions_sysvar_account) {
+        if ix.program_id == *program_id {
+            if let Ok(ix_data) = FlashLoanInstruction::try_from_slice(&ix.data) {
+                for leg in repay_legs(&ix_data, &ix.accounts) {
+                    let names_pool = leg.pool.map(|meta| meta.pubkey) == Some(*pool);
+                    if !names_pool && leg.pool_id != Some(pool_id) {
+                        continue;
+                    }
+                    if repay_index.is_some() {
+                        return Err(FlashLoanError::MultipleRepayInstructions.into());
+                    }
+                    if !names_pool {
+                        return Err(FlashLoanError::RepayPoolMismatch.into());
+                    }
+                    match leg.vault {
+                        Some(meta) if meta.pubkey == *vault => {
+                            if !meta.is_writable {
+                                return Err(FlashLoanError::RepayVaultNotWritable.into());
+                            }
+                        }
+                        _ => return Err(FlashLoanError::RepayVaultMismatch.into()),
+                    }
+                    if leg.amount != amount_owed {
+                        return Err(FlashLoanError::InvalidRepaymentAmount.into());
+                    }
+                    repay_index = Some(index);
+                }
 
-    match repay_ix_data {
-        FlashLoanInstruction::Repay {
-            pool_id: repay_pool_id,
-            amount: repay_amount,
-        } => {
-            if repay_pool_id != pool_data.pool_id || repay_amount != borrow_amount {
-                return Err(FlashLoanError::InvalidInstructionData.into());
+                if borrowed_pools(&ix_data, &ix.accounts).any(|meta| meta.pubkey == *pool) {
+                    if repay_index.is_none() {
+                        return Err(FlashLoanError::FlashLoanAlreadyTaken.into());
+                    }
+                    // A new loan against the pool, the repays from here on are its own
+                    break;
+                }
             }
         }
-        _ => return Err(FlashLoanError::InvalidInstructionData.into()),
+        index += 1;
     }
 
-    // === Transfer tokens from vault to borrower ATA ===
-    let signer_seeds: &[&[u8]] = &[b"lp_mint", &pool_id.to_le_bytes(), &[bump]];
-    let signer_seed_slice: &[&[&[u8]]] = &[signer_seeds];
+    repay_index.ok_or(FlashLoanError::LoanMustBeRepaidImmediately.into())
+}
 
-    transfer_tokens(
-        vault.clone(),
-        borrower_token_account.clone(),
-        pool.clone(), // vault authority (PDA)
-        token_program.clone(),
-        borrow_amount,
-        Some(signer_seed_slice),
-    )?;
+/// Introspected pool instructions still carry the program config the processor strips,
+/// so their account indexes are offset by it.
+const CONFIG_ACCOUNTS: usize = 1;
 
-    Ok(())
+/// One pool repaid by a later instruction, as seen through introspection.
+struct RepayLeg<'m> {
+    pool_id: Option<u64>,             // only Repay carries the pool id in its data
+    pool: Option<&'m AccountMeta>,
+    vault: Option<&'m AccountMeta>,
+    amount: u64,
+}
+
+/// Every pool an instruction repays.
+fn repay_legs<'m>(
+    ix_data: &FlashLoanInstruction,
+    accounts: &'m [AccountMeta],
+) -> Vec<RepayLeg<'m>> {
+    match ix_data {
+        FlashLoanInstruction::Repay { pool_id, amount } => vec![RepayLeg {
+            pool_id: Some(*pool_id),
+            pool: accounts.get(CONFIG_ACCOUNTS + 1),
+            vault: accounts.get(CONFIG_ACCOUNTS + 3),
+            amount: *amount,
+        }],
+        FlashLoanInstruction::RepayMany { amounts } => amounts
+            .iter()
+            .enumerate()
+            .map(|(leg, amount)| {
+                // Legs start after the borrower and the instructions sysvar
+                let pool_index = CONFIG_ACCOUNTS + 2 + leg * ACCOUNTS_PER_LEG;
+                RepayLeg {
+                    pool_id: None,
+                    pool: accounts.get(pool_index),
+                    vault: accounts.get(pool_index + 2),
+                    amount: *amount,
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Pool account of every loan an instruction opens.
+fn borrowed_pools<'m>(
+    ix_data: &FlashLoanInstruction,
+    accounts: &'m [AccountMeta],
+) -> impl Iterator<Item = &'m AccountMeta> {
+    let pool_indexes: Vec<usize> = match ix_data {
+        FlashLoanInstruction::Borrow { .. } | FlashLoanInstruction::FlashLoan { .. } => vec![CONFIG_ACCOUNTS + 1],
+        // Legs start after the borrower and the instructions sysvar
+        FlashLoanInstruction::BorrowMany { amounts, .. } => {
+            (0..amounts.len()).map(|leg| CONFIG_ACCOUNTS + 2 + leg * ACCOUNTS_PER_LEG).collect()
+        }
+        _ => Vec::new(),
+    };
+    pool_indexes.into_iter().filter_map(move |index| accounts.get(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client, pda::{find_pool_address, find_vault_address}, state::PoolStatus};
+    use solana_program::{
+        instruction::Instruction,
+        program_pack::Pack,
+        system_program,
+        sysvar::{
+            self,
+            instructions::{construct_instructions_data, store_current_index, BorrowedAccountMeta, BorrowedInstruction},
+        },
+    };
+    use spl_token_2022::state::Mint;
+    use std::{
+        collections::HashMap,
+        future::Future,
+        task::{Context, Poll, Waker},
+    };
+
+    const POOL_ID: u64 = 7;
+    const OWED: u64 = 1_009;
+
+    /// Data of the instructions sysvar for a transaction made of `instructions`,
+    /// currently executing the one at `current_index`.
+    fn instructions_sysvar_data(instructions: &[Instruction], current_index: u16) -> Vec<u8> {
+        let borrowed: Vec<BorrowedInstruction> = instructions
+            .iter()
+            .map(|ix| BorrowedInstruction {
+                program_id: &ix.program_id,
+                accounts: ix
+                    .accounts
+                    .iter()
+                    .map(|meta| BorrowedAccountMeta {
+                        pubkey: &meta.pubkey,
+                        is_signer: meta.is_signer,
+                        is_writable: meta.is_writable,
+                    })
+                    .collect(),
+                data: &ix.data,
+            })
+            .collect();
+        let mut data = construct_instructions_data(&borrowed);
+        store_current_index(&mut data, current_index);
+        data
+    }
+
+    /// Runs `find_repay` for a loan opened by the first of `instructions`.
+    fn find_repay_in(
+        program_id: &Pubkey,
+        instructions: &[Instruction],
+        pool: &Pubkey,
+        vault: &Pubkey,
+        amount_owed: u64,
+    ) -> Result<usize, ProgramError> {
+        let key = sysvar::instructions::id();
+        let mut lamports = 0;
+        let mut data = instructions_sysvar_data(instructions, 0);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &key, false, 0);
+        find_repay(program_id, &account, 0, POOL_ID, pool, vault, amount_owed)
+    }
+
+    /// A pool and its vault.
+    fn pool() -> (Pubkey, Pubkey) {
+        (Pubkey::new_unique(), Pubkey::new_unique())
+    }
+
+    fn instruction(program_id: &Pubkey, accounts: Vec<AccountMeta>, data: &FlashLoanInstruction) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: borsh::to_vec(data).unwrap(),
+        }
+    }
+
+    fn borrow_ix(program_id: &Pubkey, (pool, vault): (Pubkey, Pubkey)) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // config
+            AccountMeta::new_readonly(Pubkey::new_unique(), true),    // borrower
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(Pubkey::new_unique(), false),            // borrower token account
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // mint
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // token program
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        instruction(program_id, accounts, &FlashLoanInstruction::Borrow { pool_id: POOL_ID, amount: 1_000, mint: Pubkey::new_unique() })
+    }
+
+    fn repay_ix(program_id: &Pubkey, pool: Pubkey, vault: AccountMeta, amount: u64) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // config
+            AccountMeta::new_readonly(Pubkey::new_unique(), true),    // borrower
+            AccountMeta::new(pool, false),
+            AccountMeta::new(Pubkey::new_unique(), false),            // source
+            vault,
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // mint
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // token program
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        instruction(program_id, accounts, &FlashLoanInstruction::Repay { pool_id: POOL_ID, amount })
+    }
+
+    /// One (pool, source, vault, mint, token program) leg per (pool, vault meta, amount).
+    fn repay_many_ix(program_id: &Pubkey, legs: &[(Pubkey, AccountMeta, u64)]) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // config
+            AccountMeta::new_readonly(Pubkey::new_unique(), true),    // borrower
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        for (pool, vault, _) in legs {
+            accounts.extend([
+                AccountMeta::new(*pool, false),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                vault.clone(),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ]);
+        }
+        let amounts = legs.iter().map(|(_, _, amount)| *amount).collect();
+        instruction(program_id, accounts, &FlashLoanInstruction::RepayMany { amounts })
+    }
+
+    /// One (pool, vault, destination, mint, token program) leg per pool.
+    fn borrow_many_ix(program_id: &Pubkey, pools: &[(Pubkey, Pubkey)]) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),   // config
+            AccountMeta::new_readonly(Pubkey::new_unique(), true),    // borrower
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        for (pool, vault) in pools {
+            accounts.extend([
+                AccountMeta::new(*pool, false),
+                AccountMeta::new(*vault, false),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ]);
+        }
+        instruction(program_id, accounts, &FlashLoanInstruction::BorrowMany { amounts: vec![1_000; pools.len()] })
+    }
+
+    fn legs_of(ix: &Instruction) -> Vec<(Pubkey, Pubkey, bool, u64)> {
+        let ix_data = FlashLoanInstruction::try_from_slice(&ix.data).unwrap();
+        repay_legs(&ix_data, &ix.accounts)
+            .into_iter()
+            .map(|leg| (leg.pool.unwrap().pubkey, leg.vault.unwrap().pubkey, leg.vault.unwrap().is_writable, leg.amount))
+            .collect()
+    }
+
+    fn borrowed_by(ix: &Instruction) -> Vec<Pubkey> {
+        let ix_data = FlashLoanInstruction::try_from_slice(&ix.data).unwrap();
+        borrowed_pools(&ix_data, &ix.accounts).map(|meta| meta.pubkey).collect()
+    }
+
+    #[test]
+    fn repay_legs_read_the_pool_and_vault_of_every_leg() {
+        let program_id = Pubkey::new_unique();
+        let (a, b) = (pool(), pool());
+
+        let repay = repay_ix(&program_id, a.0, AccountMeta::new(a.1, false), OWED);
+        assert_eq!(legs_of(&repay), vec![(a.0, a.1, true, OWED)]);
+
+        let repay_many = repay_many_ix(&program_id, &[
+            (a.0, AccountMeta::new(a.1, false), OWED),
+            (b.0, AccountMeta::new_readonly(b.1, false), 2),
+        ]);
+        assert_eq!(legs_of(&repay_many), vec![(a.0, a.1, true, OWED), (b.0, b.1, false, 2)]);
+
+        assert!(legs_of(&borrow_ix(&program_id, a)).is_empty());
+        assert!(legs_of(&borrow_many_ix(&program_id, &[a])).is_empty());
+    }
+
+    #[test]
+    fn borrowed_pools_read_every_leg() {
+        let program_id = Pubkey::new_unique();
+        let (a, b) = (pool(), pool());
+
+        assert_eq!(borrowed_by(&borrow_ix(&program_id, a)), vec![a.0]);
+        assert_eq!(borrowed_by(&borrow_many_ix(&program_id, &[a, b])), vec![a.0, b.0]);
+        assert!(borrowed_by(&repay_ix(&program_id, a.0, AccountMeta::new(a.1, false), OWED)).is_empty());
+        assert!(borrowed_by(&repay_many_ix(&program_id, &[(a.0, AccountMeta::new(a.1, false), OWED)])).is_empty());
+    }
+
+    #[test]
+    fn find_repay_needs_exactly_one_repay() {
+        let program_id = Pubkey::new_unique();
+        let a = pool();
+        let borrow = borrow_ix(&program_id, a);
+        let repay = repay_ix(&program_id, a.0, AccountMeta::new(a.1, false), OWED);
+
+        assert_eq!(
+            find_repay_in(&program_id, std::slice::from_ref(&borrow), &a.0, &a.1, OWED),
+            Err(FlashLoanError::LoanMustBeRepaidImmediately.into()),
+        );
+        assert_eq!(find_repay_in(&program_id, &[borrow.clone(), repay.clone()], &a.0, &a.1, OWED), Ok(1));
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), repay.clone(), repay.clone()], &a.0, &a.1, OWED),
+            Err(FlashLoanError::MultipleRepayInstructions.into()),
+        );
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow, repay], &a.0, &a.1, OWED + 1),
+            Err(FlashLoanError::InvalidRepaymentAmount.into()),
+        );
+    }
+
+    #[test]
+    fn find_repay_matches_a_repay_many_leg() {
+        let program_id = Pubkey::new_unique();
+        let (a, b) = (pool(), pool());
+        let borrow = borrow_many_ix(&program_id, &[a, b]);
+        let repay = repay_many_ix(&program_id, &[
+            (a.0, AccountMeta::new(a.1, false), OWED),
+            (b.0, AccountMeta::new(b.1, false), 2),
+        ]);
+
+        assert_eq!(find_repay_in(&program_id, &[borrow.clone(), repay.clone()], &a.0, &a.1, OWED), Ok(1));
+        assert_eq!(find_repay_in(&program_id, &[borrow.clone(), repay.clone()], &b.0, &b.1, 2), Ok(1));
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow, repay.clone(), repay], &b.0, &b.1, 2),
+            Err(FlashLoanError::MultipleRepayInstructions.into()),
+        );
+    }
+
+    #[test]
+    fn find_repay_rejects_a_repay_of_the_wrong_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (a, other) = (pool(), pool());
+        let borrow = borrow_ix(&program_id, a);
+
+        // Repay carries the pool id, so naming another pool account is caught
+        let wrong_pool = repay_ix(&program_id, other.0, AccountMeta::new(a.1, false), OWED);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), wrong_pool], &a.0, &a.1, OWED),
+            Err(FlashLoanError::RepayPoolMismatch.into()),
+        );
+
+        let wrong_vault = repay_ix(&program_id, a.0, AccountMeta::new(other.1, false), OWED);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), wrong_vault], &a.0, &a.1, OWED),
+            Err(FlashLoanError::RepayVaultMismatch.into()),
+        );
+
+        let read_only_vault = repay_ix(&program_id, a.0, AccountMeta::new_readonly(a.1, false), OWED);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), read_only_vault], &a.0, &a.1, OWED),
+            Err(FlashLoanError::RepayVaultNotWritable.into()),
+        );
+
+        let wrong_vault = repay_many_ix(&program_id, &[(a.0, AccountMeta::new(other.1, false), OWED)]);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), wrong_vault], &a.0, &a.1, OWED),
+            Err(FlashLoanError::RepayVaultMismatch.into()),
+        );
+
+        let read_only_vault = repay_many_ix(&program_id, &[(a.0, AccountMeta::new_readonly(a.1, false), OWED)]);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow, read_only_vault], &a.0, &a.1, OWED),
+            Err(FlashLoanError::RepayVaultNotWritable.into()),
+        );
+    }
+
+    #[test]
+    fn find_repay_stops_at_the_next_loan_of_the_pool() {
+        let program_id = Pubkey::new_unique();
+        let a = pool();
+        let borrow = borrow_ix(&program_id, a);
+        let repay = repay_ix(&program_id, a.0, AccountMeta::new(a.1, false), OWED);
+
+        // Borrow, Repay, Borrow, Repay: each borrow finds its own repay
+        let transaction = [borrow.clone(), repay.clone(), borrow.clone(), repay.clone()];
+        assert_eq!(find_repay_in(&program_id, &transaction, &a.0, &a.1, OWED), Ok(1));
+        assert_eq!(find_repay_in(&program_id, &transaction[2..], &a.0, &a.1, OWED), Ok(1));
+
+        // but a second borrow before the repay is still rejected
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow.clone(), borrow_many_ix(&program_id, &[a]), repay], &a.0, &a.1, OWED),
+            Err(FlashLoanError::FlashLoanAlreadyTaken.into()),
+        );
+    }
+
+    /// Polls a client builder to completion, the fetches in these tests resolve at once.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = std::pin::pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => unreachable!("account fetches resolve immediately"),
+        }
+    }
+
+    /// Stores pool `pool_id` on `token_program`, and its mint unless it is a lamport pool,
+    /// in `accounts`. Returns the pool and its vault.
+    fn add_pool(
+        program_id: &Pubkey,
+        pool_id: u64,
+        token_program: Pubkey,
+        accounts: &mut HashMap<Pubkey, Vec<u8>>,
+    ) -> (Pubkey, Pubkey) {
+        let (pool, _) = find_pool_address(program_id, pool_id);
+        let (vault, _) = find_vault_address(program_id, &pool);
+        let token_mint = if token_program == system_program::id() {
+            spl_token::native_mint::id()
+        } else {
+            let mut mint_data = vec![0; Mint::LEN];
+            Mint { decimals: 6, is_initialized: true, ..Default::default() }.pack_into_slice(&mut mint_data);
+            let mint = Pubkey::new_unique();
+            accounts.insert(mint, mint_data);
+            mint
+        };
+        let pool_data = PoolState { pool_id, token_mint, token_program, vault, ..Default::default() };
+        accounts.insert(pool, borsh::to_vec(&pool_data).unwrap());
+        (pool, vault)
+    }
+
+    fn set_vault_read_only(ix: &mut Instruction, vault: &Pubkey) {
+        ix.accounts.iter_mut().find(|meta| meta.pubkey == *vault).unwrap().is_writable = false;
+    }
+
+    #[test]
+    fn find_repay_reads_the_client_repay_layout() {
+        let program_id = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+        let (pool, vault) = add_pool(&program_id, POOL_ID, spl_token::id(), &mut accounts);
+        let fetch = |address: Pubkey| {
+            let data = accounts.get(&address).cloned();
+            async move { Ok(data) }
+        };
+        let borrow = block_on(client::borrow(&program_id, &borrower, POOL_ID, 1_000, fetch)).unwrap();
+        let mut repay = block_on(client::repay(&program_id, &borrower, POOL_ID, OWED, fetch)).unwrap();
+        let other = Pubkey::new_unique();
+
+        let transaction = [borrow.clone(), repay.clone()];
+        assert_eq!(find_repay_in(&program_id, &transaction, &pool, &vault, OWED), Ok(1));
+        assert_eq!(
+            find_repay_in(&program_id, &transaction, &other, &vault, OWED),
+            Err(FlashLoanError::RepayPoolMismatch.into()),
+        );
+        assert_eq!(
+            find_repay_in(&program_id, &transaction, &pool, &other, OWED),
+            Err(FlashLoanError::RepayVaultMismatch.into()),
+        );
+
+        set_vault_read_only(&mut repay, &vault);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow, repay], &pool, &vault, OWED),
+            Err(FlashLoanError::RepayVaultNotWritable.into()),
+        );
+    }
+
+    #[test]
+    fn find_repay_reads_the_client_batch_layouts() {
+        let program_id = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+        let (token_pool, token_vault) = add_pool(&program_id, POOL_ID, spl_token::id(), &mut accounts);
+        let (sol_pool, sol_vault) = add_pool(&program_id, POOL_ID + 1, system_program::id(), &mut accounts);
+        let fetch = |address: Pubkey| {
+            let data = accounts.get(&address).cloned();
+            async move { Ok(data) }
+        };
+        let loans = [(POOL_ID, 1_000), (POOL_ID + 1, 2_000)];
+        let repayments = [(POOL_ID, OWED), (POOL_ID + 1, 2)];
+        let borrow_many = block_on(client::borrow_many(&program_id, &borrower, &loans, fetch)).unwrap();
+        let mut repay_many = block_on(client::repay_many(&program_id, &borrower, &repayments, fetch)).unwrap();
+        let other = Pubkey::new_unique();
+
+        let transaction = [borrow_many.clone(), repay_many.clone()];
+        assert_eq!(find_repay_in(&program_id, &transaction, &token_pool, &token_vault, OWED), Ok(1));
+        assert_eq!(find_repay_in(&program_id, &transaction, &sol_pool, &sol_vault, 2), Ok(1));
+        // RepayMany carries no pool ids, a leg for another pool doesn't repay this one
+        assert_eq!(
+            find_repay_in(&program_id, &transaction, &other, &token_vault, OWED),
+            Err(FlashLoanError::LoanMustBeRepaidImmediately.into()),
+        );
+        assert_eq!(
+            find_repay_in(&program_id, &transaction, &sol_pool, &other, 2),
+            Err(FlashLoanError::RepayVaultMismatch.into()),
+        );
+
+        // A second BorrowMany of the pool before its repay is caught at its leg
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow_many.clone(), borrow_many.clone(), repay_many.clone()], &sol_pool, &sol_vault, 2),
+            Err(FlashLoanError::FlashLoanAlreadyTaken.into()),
+        );
+
+        set_vault_read_only(&mut repay_many, &sol_vault);
+        assert_eq!(
+            find_repay_in(&program_id, &[borrow_many, repay_many], &sol_pool, &sol_vault, 2),
+            Err(FlashLoanError::RepayVaultNotWritable.into()),
+        );
+    }
+
+    /// Runs `borrow` of 1_000 tokens over `keys` (borrower, pool, vault, borrower token
+    /// account, mint, token program, instructions sysvar) in a transaction made of that
+    /// Borrow alone, with `pool_data` in the pool account.
+    fn run_borrow(program_id: &Pubkey, keys: &[Pubkey; 7], pool_data: &PoolState) -> ProgramResult {
+        let borrow_ix = Instruction {
+            program_id: *program_id,
+            accounts: keys.iter().map(|key| AccountMeta::new(*key, false)).collect(),
+            data: borsh::to_vec(&FlashLoanInstruction::Borrow { pool_id: POOL_ID, amount: 1_000, mint: keys[4] }).unwrap(),
+        };
+        let mut lamports = [0u64; 7];
+        let mut data: [Vec<u8>; 7] = Default::default();
+        data[1] = borsh::to_vec(pool_data).unwrap();
+        data[6] = instructions_sysvar_data(&[borrow_ix], 0);
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| AccountInfo::new(key, true, true, lamports, data, program_id, false, 0))
+            .collect();
+        borrow(program_id, &accounts, POOL_ID, 1_000, &keys[4])
+    }
+
+    #[test]
+    fn borrow_fails_while_the_pool_is_paused() {
+        let program_id = Pubkey::new_unique();
+        let (pool, bump) = find_pool_address(&program_id, POOL_ID);
+        let (vault, vault_bump) = find_vault_address(&program_id, &pool);
+        let mint = Pubkey::new_unique();
+        let keys = [
+            Pubkey::new_unique(),
+            pool,
+            vault,
+            Pubkey::new_unique(),
+            mint,
+            spl_token::id(),
+            sysvar::instructions::id(),
+        ];
+
+        for (status, paused) in [
+            (PoolStatus::Active, false),
+            (PoolStatus::BorrowPaused, true),
+            (PoolStatus::DepositPaused, false),
+            (PoolStatus::WithdrawOnly, true),
+        ] {
+            let pool_data = PoolState {
+                token_mint: mint,
+                token_program: spl_token::id(),
+                vault,
+                pool_id: POOL_ID,
+                status,
+                bump,
+                vault_bump,
+                ..Default::default()
+            };
+            let result = run_borrow(&program_id, &keys, &pool_data);
+            if paused {
+                assert_eq!(result, Err(FlashLoanError::PoolPaused.into()), "{status:?}");
+            } else {
+                // Past the status check, the borrower token account that was never created is next
+                assert_eq!(result, Err(FlashLoanError::InvalidAccountData.into()), "{status:?}");
+            }
+        }
+    }
 }
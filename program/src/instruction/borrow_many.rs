@@ -0,0 +1,119 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::instructions::load_current_index_checked,
+};
+
+use crate::{
+    error::FlashLoanError,
+    instruction::{close_loan, find_repay, open_loan, LoanAccounts},
+    state::{Pool as PoolState, ProgramConfig},
+    utils::invoked_via_cpi,
+};
+use borsh::BorshDeserialize;
+/// Each leg of a batched loan is five accounts in `remaining_accounts`, the last one
+/// the leg's own token program so a batch can mix SPL Token, Token-2022 and lamport pools.
+pub const ACCOUNTS_PER_LEG: usize = 5;
+
+/// Splits the accounts after the fixed ones into one leg per amount and the transfer
+/// hook accounts that follow the legs, shared by every leg's transfer.
+fn split_legs<'b, 'a>(
+    accounts: &'b [AccountInfo<'a>],
+    leg_count: usize,
+) -> Result<(&'b [AccountInfo<'a>], &'b [AccountInfo<'a>]), ProgramError> {
+    let legs_len = leg_count
+        .checked_mul(ACCOUNTS_PER_LEG)
+        .ok_or(FlashLoanError::MathError)?;
+    if leg_count == 0 || accounts.len() < legs_len {
+        return Err(FlashLoanError::InvalidInstructionData.into());
+    }
+    Ok(accounts.split_at(legs_len))
+}
+
+pub fn borrow_many(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // === Account Inputs ===
+    let borrower = next_account_info(account_info_iter)?;                 // signer
+    let instructions_sysvar_account = next_account_info(account_info_iter)?; // instruction sysvar
+    // (pool, vault, destination, mint, token program) legs, then any transfer hook accounts
+    let (legs, transfer_hook_accounts) = split_legs(account_info_iter.as_slice(), amounts.len())?;
+
+    // Batched loans are only enforced through introspection of the repays that follow
+    if invoked_via_cpi() {
+        return Err(FlashLoanError::LoanModeMismatch.into());
+    }
+
+    let current_ix_index = load_current_index_checked(instructions_sysvar_account)?;
+
+    // === Open one loan per pool, each with its own fee ===
+    let mut expected_repays = Vec::with_capacity(amounts.len());
+    for (leg, amount) in legs.chunks(ACCOUNTS_PER_LEG).zip(amounts) {
+        let loan = LoanAccounts {
+            borrower,
+            pool: &leg[0],
+            vault: &leg[1],
+            borrower_token_account: &leg[2],
+            mint: &leg[3],
+            token_program: &leg[4],
+            transfer_hook_accounts,
+        };
+        let amount_owed = open_loan(program_id, &loan, amount, current_ix_index)?;
+        let pool_data = PoolState::try_from_slice(&leg[0].try_borrow_data()?)?;
+        expected_repays.push((pool_data.pool_id, *leg[0].key, pool_data.vault, amount_owed));
+    }
+
+    // === Every pool needs exactly one later repay, usually a single RepayMany ===
+    for (pool_id, pool, vault, amount_owed) in expected_repays {
+        find_repay(
+            program_id,
+            instructions_sysvar_account,
+            current_ix_index,
+            pool_id,
+            &pool,
+            &vault,
+            amount_owed,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn repay_many(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &ProgramConfig,
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // === Account Inputs ===
+    let borrower = next_account_info(account_info_iter)?;                 // signer
+    let instructions_sysvar_account = next_account_info(account_info_iter)?; // instruction sysvar
+    // (pool, source, vault, mint, token program) legs, then any transfer hook accounts
+    let (legs, transfer_hook_accounts) = split_legs(account_info_iter.as_slice(), amounts.len())?;
+
+    let current_ix_index = load_current_index_checked(instructions_sysvar_account)?;
+
+    // === Each pool is checked against its own snapshot ===
+    for (leg, amount) in legs.chunks(ACCOUNTS_PER_LEG).zip(amounts) {
+        let loan = LoanAccounts {
+            borrower,
+            pool: &leg[0],
+            vault: &leg[2],
+            borrower_token_account: &leg[1],
+            mint: &leg[3],
+            token_program: &leg[4],
+            transfer_hook_accounts,
+        };
+        close_loan(program_id, &loan, amount, current_ix_index, config)?;
+    }
+
+    Ok(())
+}
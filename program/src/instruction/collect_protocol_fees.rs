@@ -0,0 +1,83 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::FlashLoanError,
+    instruction::load_config,
+    pda::{check_pool_address, check_vault_address, pool_signer_seeds},
+    state::Pool as PoolState,
+    utils::{transfer_from_vault, unpack_token_account},
+};
+use borsh::{BorshSerialize, BorshDeserialize};
+
+/// Sends the pool's accrued protocol fees to the treasury. Anyone may crank it,
+/// the destination is fixed by the program config.
+pub fn collect_protocol_fees(
+    program_id: &Pubkey,
+    config: &AccountInfo,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool = next_account_info(account_info_iter)?;             // pool state PDA
+    let vault = next_account_info(account_info_iter)?;            // pool vault (source)
+    let treasury = next_account_info(account_info_iter)?;         // protocol fee recipient's token account
+    let token_mint = next_account_info(account_info_iter)?;       // pool's token mint
+    let token_program = next_account_info(account_info_iter)?;
+    let transfer_hook_accounts = account_info_iter.as_slice();    // extra accounts for the mint's transfer hook
+
+    let config_data = load_config(program_id, config)?;
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+    check_vault_address(program_id, pool.key, vault.key, &pool_data)?;
+    pool_data.check_token_accounts(token_mint.key, token_program.key)?;
+    if pool_data.loan_active {
+        return Err(FlashLoanError::LoanOutstanding.into());
+    }
+
+    // Lamport pools pay the recipient itself, token pools a token account it owns
+    if pool_data.is_native() {
+        if treasury.key != &config_data.protocol_fee_recipient {
+            return Err(FlashLoanError::InvalidAccountData.into());
+        }
+    } else {
+        let treasury_data = unpack_token_account(treasury)?;
+        if treasury_data.owner != config_data.protocol_fee_recipient || treasury_data.mint != pool_data.token_mint {
+            return Err(FlashLoanError::InvalidAccountData.into());
+        }
+    }
+
+    let amount = pool_data.accrued_protocol_fees;
+    if amount == 0 {
+        return Err(FlashLoanError::NoProtocolFees.into());
+    }
+
+    pool_data.accrued_protocol_fees = 0;
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    let pool_id = pool_id.to_le_bytes();
+    let signer_seeds = pool_signer_seeds(&pool_id, &pool_data.bump);
+
+    transfer_from_vault(
+        vault.clone(),
+        token_mint.clone(),
+        treasury.clone(),
+        pool.clone(), // vault authority (PDA)
+        token_program.clone(),
+        transfer_hook_accounts,
+        amount,
+        &signer_seeds,
+    )?;
+
+    Ok(())
+}
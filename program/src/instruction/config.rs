@@ -0,0 +1,279 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::FlashLoanError,
+    pda::{config_signer_seeds, find_config_address},
+    state::ProgramConfig,
+    utils::create_pda_account,
+};
+use borsh::{BorshSerialize, BorshDeserialize};
+
+/// `UpgradeableLoaderState::ProgramData` enum tag, followed by the slot and the
+/// `Option<Pubkey>` upgrade authority.
+const PROGRAM_DATA_TAG: u32 = 3;
+const UPGRADE_AUTHORITY_OFFSET: usize = 4 + 8;
+
+pub fn init_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    protocol_fee_recipient: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;        // signer, program upgrade authority
+    let config = next_account_info(account_info_iter)?;           // program config PDA
+    let program_data = next_account_info(account_info_iter)?;     // this program's ProgramData account
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // === Only the upgrade authority may configure the program ===
+    check_upgrade_authority(program_id, authority, program_data)?;
+
+    // === Create the config PDA ===
+    let (config_key, bump) = find_config_address(program_id);
+    if config.key != &config_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+
+    create_pda_account(
+        authority.clone(),
+        config.clone(),
+        ProgramConfig::LEN,
+        program_id,
+        &Rent::get()?,
+        system_program.clone(),
+        &[&config_signer_seeds(&bump)],
+    )?;
+
+    let config_data = ProgramConfig {
+        authority: *authority.key,
+        protocol_fee_recipient,
+        protocol_fee_share_bps: 0,
+        global_paused: false,
+        next_pool_id: 0,
+        bump,
+    };
+    config_data.serialize(&mut &mut config.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+/// Fails unless `program_data` is this program's ProgramData account and `authority`
+/// is the upgrade authority recorded in it.
+fn check_upgrade_authority(program_id: &Pubkey, authority: &AccountInfo, program_data: &AccountInfo) -> ProgramResult {
+    let (program_data_key, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data.key != &program_data_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    if program_data.owner != &bpf_loader_upgradeable::id() {
+        return Err(FlashLoanError::InvalidAccountData.into());
+    }
+
+    let upgrade_authority = {
+        let data = program_data.try_borrow_data()?;
+        if data.len() < UpgradeableLoaderState::size_of_programdata_metadata()
+            || data[..4] != PROGRAM_DATA_TAG.to_le_bytes()
+        {
+            return Err(FlashLoanError::InvalidAccountData.into());
+        }
+        match data[UPGRADE_AUTHORITY_OFFSET] {
+            1 => Pubkey::try_from(&data[UPGRADE_AUTHORITY_OFFSET + 1..UPGRADE_AUTHORITY_OFFSET + 33])
+                .map_err(|_| FlashLoanError::InvalidAccountData)?,
+            // Immutable programs have nobody to hand the config to
+            _ => return Err(FlashLoanError::InvalidAuthority.into()),
+        }
+    };
+    if authority.key != &upgrade_authority {
+        return Err(FlashLoanError::InvalidAuthority.into());
+    }
+    Ok(())
+}
+
+pub fn set_global_pause(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let config = next_account_info(account_info_iter)?;      // program config PDA
+
+    let mut config_data = load_config_for_authority(program_id, authority, config)?;
+    config_data.global_paused = paused;
+    config_data.serialize(&mut &mut config.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+pub fn set_protocol_fee_recipient(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    recipient: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let config = next_account_info(account_info_iter)?;      // program config PDA
+
+    let mut config_data = load_config_for_authority(program_id, authority, config)?;
+    config_data.protocol_fee_recipient = recipient;
+    config_data.serialize(&mut &mut config.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+pub fn set_protocol_fee_share(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    protocol_fee_share_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let config = next_account_info(account_info_iter)?;      // program config PDA
+
+    if protocol_fee_share_bps > 10_000 {
+        return Err(FlashLoanError::InvalidProtocolFeeShare.into());
+    }
+
+    let mut config_data = load_config_for_authority(program_id, authority, config)?;
+    config_data.protocol_fee_share_bps = protocol_fee_share_bps;
+    config_data.serialize(&mut &mut config.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+/// Fails if the protocol is paused, otherwise returns the config. A config that
+/// was never initialized counts as not paused, with no protocol fee.
+pub fn check_global_pause(program_id: &Pubkey, config: &AccountInfo) -> Result<ProgramConfig, ProgramError> {
+    let (config_key, _) = find_config_address(program_id);
+    if config.key != &config_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+
+    if config.data_is_empty() {
+        return Ok(ProgramConfig::default());
+    }
+
+    let config_data = load_config(program_id, config)?;
+    if config_data.global_paused {
+        return Err(FlashLoanError::ProtocolPaused.into());
+    }
+
+    Ok(config_data)
+}
+
+/// Reads the config account, checking it is owned by this program and sits at the
+/// config PDA for its stored bump.
+pub fn load_config(program_id: &Pubkey, config: &AccountInfo) -> Result<ProgramConfig, ProgramError> {
+    if config.owner != program_id {
+        return Err(FlashLoanError::InvalidAccountData.into());
+    }
+    let config_data = ProgramConfig::try_from_slice(&config.try_borrow_data()?)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    let config_key = Pubkey::create_program_address(&config_signer_seeds(&config_data.bump), program_id)
+        .map_err(|_| FlashLoanError::InvalidPda)?;
+    if config.key != &config_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+
+    Ok(config_data)
+}
+
+fn load_config_for_authority(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    config: &AccountInfo,
+) -> Result<ProgramConfig, ProgramError> {
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config_data = load_config(program_id, config)?;
+    if authority.key != &config_data.authority {
+        return Err(FlashLoanError::InvalidAuthority.into());
+    }
+
+    Ok(config_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ProgramData account contents: the loader's enum tag, the deploy slot and the
+    /// optional upgrade authority.
+    fn program_data(upgrade_authority: Option<Pubkey>) -> Vec<u8> {
+        let mut data = PROGRAM_DATA_TAG.to_le_bytes().to_vec();
+        data.extend(42u64.to_le_bytes());
+        match upgrade_authority {
+            Some(authority) => {
+                data.push(1);
+                data.extend(authority.to_bytes());
+            }
+            None => data.extend([0; 33]),
+        }
+        data
+    }
+
+    /// Runs `check_upgrade_authority` for `signer` against a ProgramData account owned by
+    /// `owner` that holds `upgrade_authority`.
+    fn check(signer: &Pubkey, upgrade_authority: Option<Pubkey>, owner: &Pubkey) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let (program_data_key, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        let (mut authority_lamports, mut program_data_lamports) = (0, 0);
+        let mut authority_data = Vec::new();
+        let mut data = program_data(upgrade_authority);
+        let authority = AccountInfo::new(signer, true, true, &mut authority_lamports, &mut authority_data, &program_id, false, 0);
+        let program_data = AccountInfo::new(&program_data_key, false, false, &mut program_data_lamports, &mut data, owner, false, 0);
+        check_upgrade_authority(&program_id, &authority, &program_data)
+    }
+
+    #[test]
+    fn upgrade_authority_may_init_the_config() {
+        let authority = Pubkey::new_unique();
+        assert_eq!(check(&authority, Some(authority), &bpf_loader_upgradeable::id()), Ok(()));
+    }
+
+    #[test]
+    fn other_signers_may_not_init_the_config() {
+        assert_eq!(
+            check(&Pubkey::new_unique(), Some(Pubkey::new_unique()), &bpf_loader_upgradeable::id()),
+            Err(FlashLoanError::InvalidAuthority.into()),
+        );
+    }
+
+    #[test]
+    fn immutable_programs_cannot_init_the_config() {
+        assert_eq!(
+            check(&Pubkey::new_unique(), None, &bpf_loader_upgradeable::id()),
+            Err(FlashLoanError::InvalidAuthority.into()),
+        );
+    }
+
+    #[test]
+    fn program_data_must_be_owned_by_the_upgradeable_loader() {
+        let authority = Pubkey::new_unique();
+        assert_eq!(
+            check(&authority, Some(authority), &Pubkey::new_unique()),
+            Err(FlashLoanError::InvalidAccountData.into()),
+        );
+    }
+}
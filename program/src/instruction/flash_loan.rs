@@ -13,88 +13,305 @@ use solana_program::{
 
 use borsh::{BorshSerialize, BorshDeserialize};
 
+use crate::state::PoolStatus;
+
+/// Instructions that touch pool funds take the program config PDA as their first
+/// account; the processor checks it for the global pause and strips it before the
+/// handler sees the remaining accounts.
+///
+/// Pool, vault and LP mint addresses are derived with the `pda` module and checked
+/// against the bumps stored in the pool.
+///
+/// Instructions that move pool tokens accept the accounts a Token-2022 TransferHook
+/// mint needs (the hook program, its ExtraAccountMetaList and the accounts it lists)
+/// after their fixed accounts, or after the legs of a batch. The builders in the
+/// `client` module resolve and append them.
 #[derive(ShankInstruction, BorshSerialize, BorshDeserialize)]
 pub enum FlashLoanInstruction {
     /// 0 - Initialize the flash loan pool
     ///
-    /// Creates a new pool with a vault and LP token mint.
-    #[account(0, signer, name = "user", desc = "User initializing the pool")]
-    #[account(1, writable, name = "pool", desc = "Pool state account (PDA) ")]
-    #[account(2, name = "token_mint", desc = "Token mint to be used in the pool")]
-    #[account(3, writable, name = "vault", desc = "Vault token account (PDA)")]
-    #[account(4, writable, name = "lp_mint_account", desc = "LP token mint account (PDA)")]
-    #[account(5, writable, name = "user_lp_ata", desc = "User's LP token associated token account")]
-    #[account(6, name = "token_program", desc = "SPL Token program")]
-    #[account(7, name = "clock", desc = "Clock sysvar for timestamps")]
+    /// Creates the pool, its vault and LP token mint as PDAs, plus the user's LP token
+    /// account, then deposits `initial_amount` from the user's token account. The user
+    /// pays for the accounts and becomes the pool authority. `MINIMUM_LIQUIDITY` of the
+    /// LP shares are locked in the pool for good, so `initial_amount` has to exceed it.
+    /// `fees_bps` has to be one of `FEE_TIERS`. The pool takes `ProgramConfig::next_pool_id`
+    /// as its id, so the config has to be initialized first. Mints that are non-transferable,
+    /// have a permanent delegate or a 100% transfer fee are rejected.
+    ///
+    /// The LP mint is always a Token-2022 mint with the pooled token's decimals and a
+    /// metadata extension naming it after the pooled token's symbol. Pools whose mint is
+    /// not Token-2022 pass the Token-2022 program right after the fixed accounts.
+    #[account(0, writable, name = "config", desc = "Program config PDA, checked for the global pause and holding the pool counter")]
+    #[account(1, signer, writable, name = "user", desc = "User initializing the pool, pays for the new accounts")]
+    #[account(2, writable, name = "pool", desc = "Pool state account (PDA), created here")]
+    #[account(3, name = "token_mint", desc = "Token mint to be used in the pool")]
+    #[account(4, writable, name = "user_token_account", desc = "User's token account funding the initial deposit")]
+    #[account(5, writable, name = "vault", desc = "Vault token account (PDA), created here")]
+    #[account(6, writable, name = "lp_mint_account", desc = "LP token mint account (PDA), created here")]
+    #[account(7, writable, name = "user_lp_ata", desc = "User's LP token associated token account, created here")]
     #[account(8, name = "rent", desc = "Rent sysvar for rent exemption")]
+    #[account(9, name = "token_program", desc = "SPL Token or Token-2022 program owning the token mint")]
+    #[account(10, name = "associated_token_program", desc = "SPL Associated Token Account program")]
+    #[account(11, name = "system_program", desc = "System program")]
     InitPool {
         initial_amount: u64,
         fees_bps: u16,
-        bump: u8,
         mint: Pubkey,
-        lp_mint_bump: u8,
     },
 
     /// 1 - Liquidate the pool
     ///
-    /// User deposits tokens and receives LP tokens in return.
-    #[account(0, signer, name = "user", desc = "User initiating liquidation")]
-    #[account(1, writable, name = "user_ata", desc = "User's token account to deposit")]
-    #[account(2, writable, name = "pool", desc = "Pool state account (PDA)")]
-    #[account(3, writable, name = "vault", desc = "Vault token account (PDA)")]
-    #[account(4, name = "lp_mint", desc = "LP token mint")]
-    #[account(5, name = "lp_ata", desc = "User's LP token account")]
-    #[account(6, name = "token_program", desc = "SPL Token program")]
+    /// User deposits tokens and receives LP tokens in return, priced at the pool's
+    /// current share price and rounded down. Fails if fewer than `min_lp_out` LP tokens
+    /// would be minted or the clock is past `deadline`. Only the amount that reaches
+    /// the vault after a Token-2022 transfer fee is credited. Pools whose token program
+    /// isn't Token-2022 (SPL Token, or System for a lamport pool) pass it right after the
+    /// fixed accounts.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "user", desc = "User initiating liquidation")]
+    #[account(2, writable, name = "user_ata", desc = "User's token account to deposit, the user itself for a lamport pool")]
+    #[account(3, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(4, writable, name = "vault", desc = "Vault token account (PDA)")]
+    #[account(5, name = "token_mint", desc = "Pool's token mint")]
+    #[account(6, writable, name = "lp_mint", desc = "LP token mint (PDA)")]
+    #[account(7, writable, name = "lp_ata", desc = "User's LP token account")]
+    #[account(8, name = "token_program", desc = "Token-2022 program, owner of the LP mint")]
     LiquidatePool {
-        pool_id: u8,
+        pool_id: u64,
         token_amount: u64,
+        min_lp_out: u64,
+        deadline: Option<i64>,  // unix timestamp, None never expires
     },
 
     /// 2 - De-liquidate the pool
     ///
-    /// User redeems LP tokens to get original tokens back.
-    #[account(0, signer, name = "user", desc = "User initiating de-liquidation")]
-    #[account(1, writable, name = "user_ata", desc = "User's token account to deposit")]
-    #[account(2, writable, name = "pool", desc = "Pool state account (PDA)")]
-    #[account(3, writable, name = "vault", desc = "Vault token account (PDA)")]
-    #[account(4, name = "lp_mint", desc = "LP token mint")]
-    #[account(5, name = "lp_ata", desc = "User's LP token account")]
-    #[account(6, name = "token_program", desc = "SPL Token program")]
+    /// User redeems LP tokens to get original tokens back from the vault, rounded down.
+    /// Fails if fewer than `min_tokens_out` tokens would arrive after any transfer fee
+    /// or the clock is past `deadline`. Pools whose token program isn't Token-2022 pass it
+    /// right after the fixed accounts.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "user", desc = "User initiating de-liquidation")]
+    #[account(2, writable, name = "user_ata", desc = "User's token account receiving the withdrawal, the user itself for a lamport pool")]
+    #[account(3, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(4, writable, name = "vault", desc = "Vault token account (PDA)")]
+    #[account(5, name = "token_mint", desc = "Pool's token mint")]
+    #[account(6, writable, name = "lp_mint", desc = "LP token mint (PDA)")]
+    #[account(7, writable, name = "lp_ata", desc = "User's LP token account")]
+    #[account(8, name = "token_program", desc = "Token-2022 program, owner of the LP mint")]
     DeLiquidatePool {
-        pool_id: u8,
+        pool_id: u64,
         lp_amount: u64,
+        min_tokens_out: u64,
+        deadline: Option<i64>,  // unix timestamp, None never expires
     },
 
     /// 3 - Borrow a flash loan
     ///
     /// Borrow tokens from the vault with the requirement of same-transaction repayment.
-    #[account(0, signer, name = "borrower", desc = "User borrowing tokens")]
-    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
-    #[account(2, writable, name = "vault", desc = "Pool's vault holding tokens")]
-    #[account(3, writable, name = "borrower_token_account", desc = "Borrower's token account to receive funds")]
-    #[account(4, name = "instruction_sysvar", desc = "Instruction Sysvar for CPI introspection")]
-    #[account(5, name = "token_program", desc = "SPL Token program")]
-    #[account(6, name = "clock", desc = "Clock sysvar for timestamps")]
+    ///
+    /// Exactly one later Repay must target this pool and no other borrow against the
+    /// pool may come before it. Borrow has to be a top-level instruction, invoked through
+    /// CPI it fails with `LoanModeMismatch`: introspection can't see whether the calling
+    /// program repays, so programs borrow through the callback-style FlashLoan.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "borrower", desc = "User borrowing tokens")]
+    #[account(2, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(3, writable, name = "vault", desc = "Pool's vault holding tokens")]
+    #[account(4, writable, name = "borrower_token_account", desc = "Borrower's token account to receive funds, the borrower itself for a lamport pool")]
+    #[account(5, name = "token_mint", desc = "Pool's token mint")]
+    #[account(6, name = "token_program", desc = "SPL Token or Token-2022 program, the pool's; System program for a lamport pool")]
+    #[account(7, name = "instruction_sysvar", desc = "Instruction Sysvar for introspecting the later Repay")]
     Borrow {
-        pool_id: u8,      // Ensures correct pool/vault usage
+        pool_id: u64,     // Ensures correct pool/vault usage
         amount: u64,      // Amount to borrow
         mint: Pubkey,     // Token mint being borrowed
-        bump: u8,         // PDA bump for vault authority
     },
 
     /// 4 - Repay flash loan
     ///
-    /// Repay the borrowed amount to the vault.
-    #[account(0, signer, name = "borrower", desc = "User repaying the loan")]
-    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
-    #[account(2, writable, name = "source", desc = "User's token account sending repayment")]
-    #[account(3, writable, name = "vault", desc = "Vault receiving the repayment")]
-    #[account(4, name = "token_program", desc = "SPL Token program")]
-    #[account(5, name = "clock", desc = "Clock sysvar for timestamps")]
+    /// Repay the borrowed amount plus fee to the vault, grossed up for the mint's
+    /// transfer fee if it has one. Fails unless the vault ends at or above the
+    /// balance snapshotted by Borrow plus the fee, or if it doesn't come after that
+    /// Borrow. Has to be a top-level instruction.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "borrower", desc = "User repaying the loan")]
+    #[account(2, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(3, writable, name = "source", desc = "User's token account sending repayment, the borrower itself for a lamport pool")]
+    #[account(4, writable, name = "vault", desc = "Vault receiving the repayment")]
+    #[account(5, name = "token_mint", desc = "Pool's token mint")]
+    #[account(6, name = "token_program", desc = "SPL Token or Token-2022 program, the pool's; System program for a lamport pool")]
+    #[account(7, name = "instruction_sysvar", desc = "Instruction Sysvar for checking the Repay comes after the Borrow")]
     Repay {
-        pool_id: u8,     // Ensures repayment targets the correct pool
+        pool_id: u64,    // Ensures repayment targets the correct pool
         amount: u64,     // Amount being repaid
     },
+
+    /// 5 - Callback-style flash loan
+    ///
+    /// Lends to the borrower, invokes `FlashLoanReceiverInstruction::ReceiveFlashLoan`
+    /// on the receiver program and checks the vault was repaid before returning.
+    /// Accounts after the fixed ones are forwarded to the receiver, and also searched
+    /// for the transfer hook accounts of the loan transfer.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "borrower", desc = "User borrowing tokens")]
+    #[account(2, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(3, writable, name = "vault", desc = "Pool's vault holding tokens")]
+    #[account(4, writable, name = "borrower_token_account", desc = "Borrower's token account to receive funds, the borrower itself for a lamport pool")]
+    #[account(5, name = "token_mint", desc = "Pool's token mint")]
+    #[account(6, name = "receiver_program", desc = "Program implementing the FlashLoanReceiver interface")]
+    #[account(7, name = "token_program", desc = "SPL Token or Token-2022 program, the pool's; System program for a lamport pool")]
+    FlashLoan {
+        pool_id: u64,     // Ensures correct pool/vault usage
+        amount: u64,      // Amount to borrow
+    },
+
+    /// 6 - Borrow from several pools at once
+    ///
+    /// Each pool is passed as a (pool, vault, borrower_token_account, token_mint,
+    /// token_program) leg after the fixed accounts and charged its own fee. Legs may mix
+    /// token programs, lamport pools included. Every pool needs exactly one later repay,
+    /// usually a single RepayMany over the same pools. Has to be a top-level instruction.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "borrower", desc = "User borrowing tokens")]
+    #[account(2, name = "instruction_sysvar", desc = "Instruction Sysvar for introspecting the later repays")]
+    BorrowMany {
+        amounts: Vec<u64>, // Amount to borrow, one per pool leg
+    },
+
+    /// 7 - Repay several pools at once
+    ///
+    /// Each pool is passed as a (pool, source, vault, token_mint, token_program) leg after the
+    /// fixed accounts and checked against its own vault snapshot.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, signer, name = "borrower", desc = "User repaying the loans")]
+    #[account(2, name = "instruction_sysvar", desc = "Instruction Sysvar for checking the repays come after the BorrowMany")]
+    RepayMany {
+        amounts: Vec<u64>, // Amount being repaid, one per pool leg
+    },
+
+    /// 8 - Update pool configuration
+    ///
+    /// Lets the pool authority change the fee, the loan and deposit caps (0 means
+    /// uncapped) and the pool status. Fields left as `None` are unchanged. A new fee has
+    /// to be one of `FEE_TIERS`.
+    #[account(0, signer, name = "authority", desc = "Pool authority")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    UpdatePoolConfig {
+        pool_id: u64,
+        fees_bps: Option<u16>,
+        max_loan_amount: Option<u64>,
+        deposit_cap: Option<u64>,
+        status: Option<PoolStatus>,
+    },
+
+    /// 9 - Propose a new pool authority
+    ///
+    /// The handoff only completes once the proposed key signs AcceptAuthority.
+    #[account(0, signer, name = "authority", desc = "Current pool authority")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    ProposeAuthority {
+        pool_id: u64,
+        new_authority: Pubkey,
+    },
+
+    /// 10 - Accept a proposed pool authority
+    #[account(0, signer, name = "new_authority", desc = "Proposed pool authority")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    AcceptAuthority {
+        pool_id: u64,
+    },
+
+    /// 11 - Renounce the pool authority
+    ///
+    /// Permanently removes the authority, making the pool's parameters immutable.
+    #[account(0, signer, name = "authority", desc = "Current pool authority")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    RenounceAuthority {
+        pool_id: u64,
+    },
+
+    /// 12 - Initialize the program config
+    ///
+    /// Creates the config PDA. Only the program's upgrade authority may call it.
+    #[account(0, signer, writable, name = "authority", desc = "Program upgrade authority, pays for the config")]
+    #[account(1, writable, name = "config", desc = "Program config PDA")]
+    #[account(2, name = "program_data", desc = "This program's ProgramData account")]
+    #[account(3, name = "system_program", desc = "System program")]
+    InitConfig {
+        protocol_fee_recipient: Pubkey,
+    },
+
+    /// 13 - Pause or resume every pool at once
+    #[account(0, signer, name = "authority", desc = "Config authority")]
+    #[account(1, writable, name = "config", desc = "Program config PDA")]
+    SetGlobalPause {
+        paused: bool,
+    },
+
+    /// 14 - Change where protocol fees are sent
+    #[account(0, signer, name = "authority", desc = "Config authority")]
+    #[account(1, writable, name = "config", desc = "Program config PDA")]
+    SetProtocolFeeRecipient {
+        recipient: Pubkey,
+    },
+
+    /// 15 - Set the treasury's share of every loan fee
+    #[account(0, signer, name = "authority", desc = "Config authority")]
+    #[account(1, writable, name = "config", desc = "Program config PDA")]
+    SetProtocolFeeShare {
+        protocol_fee_share_bps: u16,
+    },
+
+    /// 16 - Collect accrued protocol fees
+    ///
+    /// Moves the pool's accrued protocol fees from the vault to the treasury token
+    /// account owned by the config's protocol fee recipient, or to the recipient itself
+    /// for a lamport pool. Permissionless, blocked by the global pause.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause and holding the protocol fee recipient")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(2, writable, name = "vault", desc = "Pool's vault holding tokens")]
+    #[account(3, writable, name = "treasury", desc = "Protocol fee recipient's token account")]
+    #[account(4, name = "token_mint", desc = "Pool's token mint")]
+    #[account(5, name = "token_program", desc = "SPL Token or Token-2022 program, the pool's; System program for a lamport pool")]
+    CollectProtocolFees {
+        pool_id: u64,
+    },
+
+    /// 17 - Reconcile the pool with its vault
+    ///
+    /// Loan fees are credited to the LPs when a loan settles. Sync credits any other
+    /// tokens found in the vault as a donation, tracked in `Pool::total_donations`.
+    /// Permissionless, blocked by the global pause.
+    #[account(0, name = "config", desc = "Program config PDA, checked for the global pause")]
+    #[account(1, writable, name = "pool", desc = "Pool state account (PDA)")]
+    #[account(2, name = "vault", desc = "Pool's vault holding tokens")]
+    Sync {
+        pool_id: u64,
+    },
+
+    /// 18 - Initialize a SOL pool
+    ///
+    /// Like InitPool, but the vault is a program-owned PDA holding lamports, so loans
+    /// move SOL directly without wrapping. The pool records the native mint as its token
+    /// mint and the System program as its token program: Borrow, Repay, FlashLoan, the
+    /// batches and CollectProtocolFees take the System program in their `token_program`
+    /// slot and the user's own account in place of a token account. LiquidatePool and
+    /// DeLiquidatePool take the System program right after their fixed accounts. LP
+    /// tokens are Token-2022 with 9 decimals, like SOL.
+    #[account(0, writable, name = "config", desc = "Program config PDA, checked for the global pause and holding the pool counter")]
+    #[account(1, signer, writable, name = "user", desc = "User initializing the pool, pays for the new accounts and the deposit")]
+    #[account(2, writable, name = "pool", desc = "Pool state account (PDA), created here")]
+    #[account(3, writable, name = "vault", desc = "Lamport vault (PDA) owned by this program, created here")]
+    #[account(4, writable, name = "lp_mint_account", desc = "LP token mint account (PDA), created here")]
+    #[account(5, writable, name = "user_lp_ata", desc = "User's LP token associated token account, created here")]
+    #[account(6, name = "rent", desc = "Rent sysvar for rent exemption")]
+    #[account(7, name = "token_program", desc = "Token-2022 program, for the LP mint")]
+    #[account(8, name = "associated_token_program", desc = "SPL Associated Token Account program")]
+    #[account(9, name = "system_program", desc = "System program")]
+    InitNativePool {
+        initial_amount: u64,
+        fees_bps: u16,
+    },
 }
 
 impl FlashLoanInstruction {
@@ -102,4 +319,23 @@ impl FlashLoanInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         Self::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
     }
+
+    /// Whether the instruction is blocked by the global pause and therefore
+    /// takes the program config as its first account.
+    pub fn is_pausable(&self) -> bool {
+        matches!(
+            self,
+            FlashLoanInstruction::InitPool { .. }
+                | FlashLoanInstruction::InitNativePool { .. }
+                | FlashLoanInstruction::LiquidatePool { .. }
+                | FlashLoanInstruction::DeLiquidatePool { .. }
+                | FlashLoanInstruction::Borrow { .. }
+                | FlashLoanInstruction::Repay { .. }
+                | FlashLoanInstruction::FlashLoan { .. }
+                | FlashLoanInstruction::BorrowMany { .. }
+                | FlashLoanInstruction::RepayMany { .. }
+                | FlashLoanInstruction::CollectProtocolFees { .. }
+                | FlashLoanInstruction::Sync { .. }
+        )
+    }
 }
@@ -0,0 +1,83 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::FlashLoanError,
+    instruction::{open_loan, settle_loan, FlashLoanReceiverInstruction, LoanAccounts},
+    state::{Pool as PoolState, ProgramConfig},
+};
+use borsh::BorshDeserialize;
+
+pub fn flash_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &ProgramConfig,
+    pool_id: u64,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // === Account Inputs ===
+    let borrower = next_account_info(account_info_iter)?;                 // signer
+    let pool = next_account_info(account_info_iter)?;                     // pool state PDA
+    let vault = next_account_info(account_info_iter)?;                    // pool vault (source)
+    let borrower_token_account = next_account_info(account_info_iter)?;  // recipient ATA (destination)
+    let token_mint = next_account_info(account_info_iter)?;              // pool's token mint
+    let receiver_program = next_account_info(account_info_iter)?;        // program called back with the funds
+    let token_program = next_account_info(account_info_iter)?;           // token program
+    let extra_accounts = account_info_iter.as_slice();                   // forwarded to the receiver as-is, and searched for transfer hook accounts
+
+    // Reentrancy into this program is rejected by the runtime, fail early instead
+    if receiver_program.key == program_id || !receiver_program.executable {
+        return Err(FlashLoanError::InvalidAccountData.into());
+    }
+
+    let pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_id != pool_data.pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+
+    // === Open the loan before handing control to the receiver ===
+    let loan = LoanAccounts {
+        borrower,
+        pool,
+        vault,
+        borrower_token_account,
+        mint: token_mint,
+        token_program,
+        transfer_hook_accounts: extra_accounts,
+    };
+    let amount_owed = open_loan(program_id, &loan, amount, 0)?;
+    let fee = amount_owed - amount;
+
+    // === Hand the funds to the receiver ===
+    let callback_ix = FlashLoanReceiverInstruction::ReceiveFlashLoan { amount, fee }.instruction(
+        receiver_program.key,
+        borrower.key,
+        borrower_token_account.key,
+        vault.key,
+        token_mint.key,
+        token_program.key,
+        extra_accounts,
+    )?;
+
+    let mut callback_accounts = vec![
+        borrower.clone(),
+        borrower_token_account.clone(),
+        vault.clone(),
+        token_mint.clone(),
+        token_program.clone(),
+        receiver_program.clone(),
+    ];
+    callback_accounts.extend_from_slice(extra_accounts);
+
+    invoke(&callback_ix, &callback_accounts)?;
+
+    // === Reload the pool and vault and check it was made whole ===
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    settle_loan(pool, vault, &mut pool_data, config)
+}
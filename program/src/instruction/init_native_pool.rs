@@ -0,0 +1,90 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{
+    error::FlashLoanError,
+    instruction::{create_pool, NewPoolAccounts},
+    utils::{create_pda_account, transfer_to_vault},
+};
+
+/// Creates a pool lending SOL straight from a program-owned vault PDA holding lamports.
+/// The pool records the native mint and the System program as its token mint and token
+/// program; its LP tokens are issued with Token-2022 like every pool's.
+pub fn init_native_pool(
+    program_id: &Pubkey,
+    config: &AccountInfo,
+    accounts: &[AccountInfo],
+    initial_amount: u64,
+    fees_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let user = next_account_info(account_info_iter)?;             // signer, pays for the new accounts and the deposit
+    let pool_account = next_account_info(account_info_iter)?;     // pool state PDA, created here
+    let vault = next_account_info(account_info_iter)?;            // lamport vault PDA, created here
+    let lp_mint_account = next_account_info(account_info_iter)?;  // LP mint PDA, created here
+    let user_lp_ata = next_account_info(account_info_iter)?;      // user's LP token ATA, created here
+    let rent_sysvar = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;    // Token-2022, for the LP mint
+    let ata_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if token_program.key != &spl_token_2022::id() || system_program.key != &system_program::id() {
+        return Err(FlashLoanError::InvalidTokenProgram.into());
+    }
+
+    let accounts = NewPoolAccounts {
+        user,
+        pool: pool_account,
+        vault,
+        lp_mint: lp_mint_account,
+        user_lp_ata,
+        rent_sysvar,
+        lp_token_program: token_program,
+        ata_program,
+        system_program,
+    };
+    create_pool(
+        program_id,
+        config,
+        &accounts,
+        fees_bps,
+        spl_token::native_mint::id(),
+        system_program::id(),
+        spl_token::native_mint::DECIMALS,
+        Some("SOL"),
+        |rent, vault_seeds| {
+            // === Create the vault, a data-less account owned by this program so it can be debited ===
+            create_pda_account(
+                user.clone(),
+                vault.clone(),
+                0,
+                program_id,
+                rent,
+                system_program.clone(),
+                &[vault_seeds],
+            )?;
+
+            // The vault's rent-exempt reserve is on top of the deposit and never lent out
+            transfer_to_vault(
+                user.clone(),
+                system_program.clone(),
+                vault.clone(),
+                user.clone(),
+                system_program.clone(),
+                &[],
+                initial_amount,
+            )?;
+            Ok(initial_amount)
+        },
+    )
+}
@@ -11,100 +11,345 @@ use solana_program::{
 
 use borsh::BorshDeserialize;
 use crate::{
-    state::Pool as PoolState,
-    utils::{transfer_tokens, create_mint, mint_tokens},
+    instruction::load_config,
+    pda::{
+        find_lp_mint_address, find_pool_address, find_vault_address,
+        lp_mint_signer_seeds, pool_signer_seeds, vault_signer_seeds,
+    },
+    state::{lp_token_metadata, Pool as PoolState},
+    utils::{
+        check_mint_extensions, check_token_program, create_ata, create_mint, create_pda_account,
+        init_metadata_pointer, init_token_account, init_token_metadata, mint_tokens, token_account_len,
+        token_metadata_symbol, transfer_fee, transfer_tokens, unpack_mint,
+    },
     error::FlashLoanError,
 };
 use borsh::BorshSerialize;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::ExtensionType,
+    state::Mint,
+};
+use spl_token_metadata_interface::state::TokenMetadata;
 
 pub fn init_pool(
     program_id: &Pubkey,
+    config: &AccountInfo,
     accounts: &[AccountInfo],
     initial_amount: u64,
     fees_bps: u16,
     mint: Pubkey,
-    lp_mint_bump: u8,
-    bump: u8,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
-    let user = next_account_info(account_info_iter)?;             // signer
-    let pool_account = next_account_info(account_info_iter)?;     // pool state PDA
-    let mint = next_account_info(account_info_iter)?;             // input token mint
-    let vault = next_account_info(account_info_iter)?;            // token vault
-    let lp_mint_account = next_account_info(account_info_iter)?;  // LP mint PDA
-    let user_lp_ata = next_account_info(account_info_iter)?;      // user's LP token ATA
+    let user = next_account_info(account_info_iter)?;             // signer, pays for the new accounts
+    let pool_account = next_account_info(account_info_iter)?;     // pool state PDA, created here
+    let token_mint = next_account_info(account_info_iter)?;       // input token mint
+    let user_token_account = next_account_info(account_info_iter)?; // source of the initial deposit
+    let vault = next_account_info(account_info_iter)?;            // vault PDA, created here
+    let lp_mint_account = next_account_info(account_info_iter)?;  // LP mint PDA, created here
+    let user_lp_ata = next_account_info(account_info_iter)?;      // user's LP token ATA, created here
     let rent_sysvar = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
-    let clock_sysvar = next_account_info(account_info_iter)?;
+    let ata_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let remaining_accounts = account_info_iter.as_slice();        // LP token program if not token_program, then transfer hook accounts
 
     if !user.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    if initial_amount == 0 {
-        return Err(FlashLoanError::InvalidAmount.into());
+    if token_mint.key != &mint {
+        return Err(FlashLoanError::InvalidTokenMint.into());
     }
 
-    let rent = &Rent::from_account_info(rent_sysvar)?;
-    if !rent.is_exempt(pool_account.lamports(), pool_account.data_len()) {
-        return Err(ProgramError::AccountNotRentExempt);
+    // === The pool runs on whichever token program owns the mint ===
+    check_token_program(token_program.key)?;
+    if token_mint.owner != token_program.key {
+        return Err(FlashLoanError::InvalidTokenProgram.into());
     }
+    check_mint_extensions(token_mint)?;
 
-    // Transfer tokens to vault
-    transfer_tokens(
-        user.clone(),
-        vault.clone(),
+    // === LP mints are Token-2022, other pools pass it right after the fixed accounts ===
+    let (lp_token_program, transfer_hook_accounts) = if token_program.key == &spl_token_2022::id() {
+        (token_program, remaining_accounts)
+    } else {
+        let (lp_token_program, transfer_hook_accounts) = remaining_accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if lp_token_program.key != &spl_token_2022::id() {
+            return Err(FlashLoanError::InvalidTokenProgram.into());
+        }
+        (lp_token_program, transfer_hook_accounts)
+    };
+
+    let accounts = NewPoolAccounts {
+        user,
+        pool: pool_account,
+        vault,
+        lp_mint: lp_mint_account,
+        user_lp_ata,
+        rent_sysvar,
+        lp_token_program,
+        ata_program,
+        system_program,
+    };
+    create_pool(
+        program_id,
+        config,
+        &accounts,
+        fees_bps,
+        *token_mint.key,
+        *token_program.key,
+        unpack_mint(token_mint)?.decimals,
+        token_metadata_symbol(token_mint)?.as_deref(),
+        |rent, vault_seeds| {
+            // === Create the vault, a token account owned by the pool ===
+            create_pda_account(
+                user.clone(),
+                vault.clone(),
+                token_account_len(token_mint)?,
+                token_program.key,
+                rent,
+                system_program.clone(),
+                &[vault_seeds],
+            )?;
+            init_token_account(
+                vault.clone(),
+                token_mint.clone(),
+                pool_account.key,
+                token_program.clone(),
+            )?;
+
+            // Transfer tokens to vault
+            transfer_tokens(
+                user_token_account.clone(),
+                token_mint.clone(),
+                vault.clone(),
+                user.clone(),
+                token_program.clone(),
+                transfer_hook_accounts,
+                initial_amount,
+                None,
+            )?;
+
+            // Only what reached the vault after the mint's transfer fee counts as liquidity
+            initial_amount
+                .checked_sub(transfer_fee(token_mint, initial_amount)?)
+                .ok_or(FlashLoanError::MathError.into())
+        },
+    )
+}
+
+/// Accounts every new pool is created with, whatever its vault holds.
+pub(crate) struct NewPoolAccounts<'a, 'b> {
+    pub user: &'b AccountInfo<'a>,
+    pub pool: &'b AccountInfo<'a>,
+    pub vault: &'b AccountInfo<'a>,
+    pub lp_mint: &'b AccountInfo<'a>,
+    pub user_lp_ata: &'b AccountInfo<'a>,
+    pub rent_sysvar: &'b AccountInfo<'a>,
+    pub lp_token_program: &'b AccountInfo<'a>,
+    pub ata_program: &'b AccountInfo<'a>,
+    pub system_program: &'b AccountInfo<'a>,
+}
+
+/// Creates a pool lending `token_mint`: takes its id, creates the pool state and LP
+/// accounts and mints the creator's LP tokens. `fund_vault` creates the vault from its
+/// signer seeds, moves the initial deposit in and returns the amount that arrived.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_pool<'a>(
+    program_id: &Pubkey,
+    config: &AccountInfo,
+    accounts: &NewPoolAccounts<'a, '_>,
+    fees_bps: u16,
+    token_mint: Pubkey,
+    token_program: Pubkey,
+    decimals: u8,
+    symbol: Option<&str>,
+    fund_vault: impl FnOnce(&Rent, &[&[u8]]) -> Result<u64, ProgramError>,
+) -> ProgramResult {
+    let NewPoolAccounts { user, pool, vault, lp_mint, user_lp_ata, .. } = *accounts;
+    PoolState::validate_fees_bps(fees_bps)?;
+
+    let pool_id = take_next_pool_id(program_id, config)?;
+    let (bump, vault_bump, lp_mint_bump) = check_new_pool_addresses(program_id, pool_id, pool, vault, lp_mint)?;
+    if user_lp_ata.key != &get_associated_token_address_with_program_id(user.key, lp_mint.key, accounts.lp_token_program.key) {
+        return Err(FlashLoanError::InvalidAccountData.into());
+    }
+
+    let pool_id_bytes = pool_id.to_le_bytes();
+    let pool_seeds = pool_signer_seeds(&pool_id_bytes, &bump);
+    let vault_seeds = vault_signer_seeds(pool.key, &vault_bump);
+    let lp_mint_seeds = lp_mint_signer_seeds(pool.key, &lp_mint_bump);
+
+    let rent = &Rent::from_account_info(accounts.rent_sysvar)?;
+
+    // === Create the pool state account ===
+    create_pda_account(
         user.clone(),
-        token_program.clone(),
-        initial_amount,
-        None,
+        pool.clone(),
+        PoolState::LEN,
+        program_id,
+        rent,
+        accounts.system_program.clone(),
+        &[&pool_seeds],
     )?;
-        //pool id is also the open_time
-    // so how to get current time
-    let clock = solana_program::sysvar::clock::Clock::from_account_info(clock_sysvar)?;
-    let open_time = clock.unix_timestamp as u8;
-    let pool_id = open_time; // Use open_time as pool_id
 
-    let pool_id_bytes = &pool_id.to_le_bytes();
-    let signer_seeds: &[&[u8]] = &[b"lp_mint", pool_id_bytes, &[lp_mint_bump]];
-    let signer_seeds_ref: &[&[&[u8]]] = &[&signer_seeds[..]];
+    let received_amount = fund_vault(rent, &vault_seeds)?;
 
-    // Create LP mint and mint tokens to user's ATA
-    create_mint(
-        lp_mint_account.clone(),
-        &user_lp_ata.key,
-        9,
-        pool_account.clone(),
-        token_program.clone(),
-        signer_seeds_ref,
+    // === LP tokens copy the pooled token's decimals and are named after its symbol ===
+    create_lp_accounts(
+        user,
+        pool,
+        lp_mint,
+        user_lp_ata,
+        &pool_seeds,
+        &lp_mint_seeds,
+        decimals,
+        lp_token_metadata(pool_id, symbol),
+        accounts.rent_sysvar,
+        accounts.lp_token_program,
+        accounts.ata_program,
+        accounts.system_program,
     )?;
 
-
     // Initialize pool state
-    let pool_data = PoolState {
-        pool_id: open_time,
+    let mut pool_data = PoolState {
+        pool_id,
         vault: *vault.key,
-        total_liquidity: initial_amount,
-        total_lp_supply: initial_amount,
-        fees_bps: fees_bps.try_into().unwrap(),
-        token_mint: *mint.key,
-        bump: bump,
+        fees_bps,
+        token_mint,
+        token_program,
+        bump,
+        vault_bump,
         lp_mint_bump,
-        lp_mint: *lp_mint_account.key,
+        lp_mint: *lp_mint.key,
+        authority: *user.key,
+        ..Default::default()
     };
-    pool_data.serialize(&mut &mut pool_account.try_borrow_mut_data()?[..])
+    // The first MINIMUM_LIQUIDITY shares stay locked in the pool
+    let creator_lp = pool_data.init_liquidity(received_amount)?;
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
         .map_err(|_| ProgramError::InvalidAccountData)?;
-    
-        //transfer lp to the user 
+
+    //transfer lp to the user
     mint_tokens(
-        lp_mint_account.clone(),
+        lp_mint.clone(),
         user_lp_ata.clone(),
-        pool_account.clone(),
+        pool.clone(),
+        accounts.lp_token_program.clone(),
+        creator_lp,
+        &[&pool_seeds],
+    )
+}
+
+/// Takes the next pool id from the config's counter.
+pub(crate) fn take_next_pool_id(program_id: &Pubkey, config: &AccountInfo) -> Result<u64, ProgramError> {
+    let mut config_data = load_config(program_id, config)?;
+    let pool_id = config_data.next_pool_id;
+    config_data.next_pool_id = pool_id
+        .checked_add(1)
+        .ok_or(FlashLoanError::MathError)?;
+    config_data.serialize(&mut &mut config.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    Ok(pool_id)
+}
+
+/// Checks a new pool, its vault and LP mint sit at their canonical PDAs and returns
+/// their bumps.
+pub(crate) fn check_new_pool_addresses(
+    program_id: &Pubkey,
+    pool_id: u64,
+    pool: &AccountInfo,
+    vault: &AccountInfo,
+    lp_mint: &AccountInfo,
+) -> Result<(u8, u8, u8), ProgramError> {
+    let (pool_key, bump) = find_pool_address(program_id, pool_id);
+    if pool.key != &pool_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    let (vault_key, vault_bump) = find_vault_address(program_id, pool.key);
+    if vault.key != &vault_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    let (lp_mint_key, lp_mint_bump) = find_lp_mint_address(program_id, pool.key);
+    if lp_mint.key != &lp_mint_key {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    Ok((bump, vault_bump, lp_mint_bump))
+}
+
+/// Creates the LP mint with the pool as mint authority and its TokenMetadata extension
+/// holding `metadata`'s name and symbol, then the user's LP token account.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_lp_accounts<'a>(
+    user: &AccountInfo<'a>,
+    pool: &AccountInfo<'a>,
+    lp_mint: &AccountInfo<'a>,
+    user_lp_ata: &AccountInfo<'a>,
+    pool_seeds: &[&[u8]],
+    lp_mint_seeds: &[&[u8]],
+    decimals: u8,
+    metadata: (String, String),
+    rent_sysvar: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    ata_program: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (name, symbol) = metadata;
+    let rent = Rent::from_account_info(rent_sysvar)?;
+    let mint_len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])?;
+    let metadata_len = TokenMetadata {
+        name: name.clone(),
+        symbol: symbol.clone(),
+        ..Default::default()
+    }
+    .tlv_size_of()?;
+
+    create_pda_account(
+        user.clone(),
+        lp_mint.clone(),
+        mint_len,
+        token_program.key,
+        &rent,
+        system_program.clone(),
+        &[lp_mint_seeds],
+    )?;
+    // The token program grows the mint to fit the metadata, so fund that up front
+    let metadata_rent = rent
+        .minimum_balance(mint_len + metadata_len)
+        .saturating_sub(rent.minimum_balance(mint_len));
+    invoke(
+        &system_instruction::transfer(user.key, lp_mint.key, metadata_rent),
+        &[user.clone(), lp_mint.clone(), system_program.clone()],
+    )?;
+
+    init_metadata_pointer(lp_mint.clone(), pool.key, token_program.clone())?;
+    create_mint(
+        lp_mint.clone(),
+        pool.key,
+        decimals,
+        rent_sysvar.clone(),
+        token_program.clone(),
+        &[],
+    )?;
+    init_token_metadata(
+        lp_mint.clone(),
+        pool.clone(),
         token_program.clone(),
-        initial_amount,
-        signer_seeds_ref,
+        name,
+        symbol,
+        &[pool_seeds],
     )?;
-    
-    Ok(())
+
+    create_ata(
+        user.clone(),
+        user.clone(),
+        lp_mint.clone(),
+        user_lp_ata.clone(),
+        token_program.clone(),
+        ata_program.clone(),
+        system_program.clone(),
+    )
 }
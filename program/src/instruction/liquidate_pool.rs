@@ -15,68 +15,87 @@
         FlashLoanError,
         mint_tokens, 
         burn_tokens, 
-        transfer_tokens,
+        transfer_from_vault,
+        transfer_to_vault,
+        check_deadline,
+        transfer_fee,
+        pda::{check_lp_mint_address, check_pool_address, check_vault_address, pool_signer_seeds},
         state::Pool as PoolState,
     };
 
-    use borsh::BorshDeserialize;
-    use spl_associated_token_account::get_associated_token_address;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use spl_associated_token_account::get_associated_token_address_with_program_id;
 
 
 pub fn liquidate_pool(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    pool_id: u8,
+    pool_id: u64,
     token_amount: u64,
+    min_lp_out: u64,
+    deadline: Option<i64>,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let user = next_account_info(account_info_iter)?;
     let user_ata = next_account_info(account_info_iter)?;
     let pool = next_account_info(account_info_iter)?;
     let vault = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
     let lp_mint = next_account_info(account_info_iter)?;
     let lp_ata = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let remaining_accounts = account_info_iter.as_slice();
     if !user.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
+    check_deadline(deadline)?;
 
     let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
-    if pool_data.pool_id != pool_id.into() {
+    if pool_data.pool_id != pool_id {
         return Err(FlashLoanError::InvalidPoolId.into());
     }
-    if user_ata.key != &get_associated_token_address(&user.key, &pool_data.token_mint) {
-        return Err(FlashLoanError::InvalidAccountData.into());
+    check_pool_address(program_id, pool.key, &pool_data)?;
+    check_vault_address(program_id, pool.key, vault.key, &pool_data)?;
+    check_lp_mint_address(program_id, pool.key, lp_mint.key, &pool_data)?;
+    pool_data.check_lp_token_accounts(token_mint.key, token_program.key)?;
+    let (vault_program, transfer_hook_accounts) = vault_program(&pool_data, token_program, remaining_accounts)?;
+    if pool_data.loan_active {
+        return Err(FlashLoanError::LoanOutstanding.into());
+    }
+    if !pool_data.status.can_deposit() {
+        return Err(FlashLoanError::PoolPaused.into());
+    }
+    // === Only what reaches the vault after the mint's transfer fee is credited ===
+    let net_amount = token_amount
+        .checked_sub(transfer_fee(token_mint, token_amount)?)
+        .ok_or(FlashLoanError::MathError)?;
+    if pool_data.deposit_cap != 0 {
+        let liquidity_after = pool_data.total_liquidity
+            .checked_add(net_amount)
+            .ok_or(FlashLoanError::MathError)?;
+        if liquidity_after > pool_data.deposit_cap {
+            return Err(FlashLoanError::DepositCapExceeded.into());
+        }
     }
-    transfer_tokens(
+    check_user_token_account(&pool_data, user, user_ata)?;
+    // === Shares are priced before the deposit lands, rounded down ===
+    let lp_to_mint = pool_data.deposit_liquidity(net_amount)?;
+    if lp_to_mint < min_lp_out {
+        return Err(FlashLoanError::LpOutputTooLow.into());
+    }
+
+    transfer_to_vault(
         user_ata.clone(),
+        token_mint.clone(),
         vault.clone(),
         user.clone(),
-        token_program.clone(),
+        vault_program.clone(),
+        transfer_hook_accounts,
         token_amount,
-        None
     )?;
 
-    let share = token_amount
-        .checked_div(pool_data.total_liquidity)
-        .ok_or(FlashLoanError::MathError)?;
-    
-        let (lp_mint_key, _bump) = Pubkey::find_program_address(
-        &[b"lp_mint", &pool_data.pool_id.to_le_bytes()],
-        program_id,
-    );
-    if lp_mint.key != &lp_mint_key {
-        return Err(FlashLoanError::InvalidAccountData.into());
-    }
-
-    pool_data.total_liquidity += token_amount;
-    let lp_to_mint = share * pool_data.total_lp_supply;
-    if lp_to_mint == 0 {
-        return Err(FlashLoanError::InvalidAmount.into());
-    }
-    let pool_id_bytes = &pool_id.to_le_bytes();
-    let seeds = &[b"vault", pool_id_bytes.as_ref(), &[pool_data.lp_mint_bump]];
-    let signer_seeds: &[&[&[u8]]] = &[&seeds[..]];
+    let pool_id_bytes = pool_id.to_le_bytes();
+    let signer_seeds = pool_signer_seeds(&pool_id_bytes, &pool_data.bump);
 
     mint_tokens(
         lp_mint.clone(),
@@ -84,56 +103,69 @@ pub fn liquidate_pool(
         pool.clone(),
         token_program.clone(),
         lp_to_mint,
-        signer_seeds
+        &[&signer_seeds]
     )?;
 
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
     Ok(())
 }
 
 pub fn deliquidate_pool(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    pool_id: u8,
+    pool_id: u64,
     lp_amount: u64,
+    min_tokens_out: u64,
+    deadline: Option<i64>,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let user = next_account_info(account_info_iter)?;
+    let user_ata = next_account_info(account_info_iter)?;
     let pool = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
     let lp_mint = next_account_info(account_info_iter)?;
     let lp_ata = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let remaining_accounts = account_info_iter.as_slice();
     if !user.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
+    check_deadline(deadline)?;
 
     let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
     if pool_data.pool_id != pool_id {
         return Err(FlashLoanError::InvalidPoolId.into());
     }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+    check_vault_address(program_id, pool.key, vault.key, &pool_data)?;
+    check_lp_mint_address(program_id, pool.key, lp_mint.key, &pool_data)?;
+    pool_data.check_lp_token_accounts(token_mint.key, token_program.key)?;
+    let (vault_program, transfer_hook_accounts) = vault_program(&pool_data, token_program, remaining_accounts)?;
+    if pool_data.loan_active {
+        return Err(FlashLoanError::LoanOutstanding.into());
+    }
+    // Withdrawals are never blocked by the pool status, LPs can always exit
 
-    if lp_mint.key != &pool_data.lp_mint {
+    check_user_token_account(&pool_data, user, user_ata)?;
+    //verify that the mint of the ata matches the lp_mint
+    if lp_ata.key != &get_associated_token_address_with_program_id(user.key, lp_mint.key, token_program.key) {
         return Err(FlashLoanError::InvalidAccountData.into());
     }
 
-    let share = lp_amount
-        .checked_div(pool_data.total_lp_supply)
+    // === Payout is rounded down so the remaining LPs never lose value ===
+    let tokens_out = pool_data.withdraw_liquidity(lp_amount)?;
+    // Slippage is checked on what the user receives after the mint's transfer fee
+    let tokens_received = tokens_out
+        .checked_sub(transfer_fee(token_mint, tokens_out)?)
         .ok_or(FlashLoanError::MathError)?;
-
-    let tokens_out = share
-        .checked_mul(pool_data.total_liquidity)
-        .ok_or(FlashLoanError::MathError)?;
-
-    if tokens_out == 0 {
-        return Err(FlashLoanError::InvalidAmount.into());
+    if tokens_received < min_tokens_out {
+        return Err(FlashLoanError::TokenOutputTooLow.into());
     }
 
     //burn the mint token
-    let ata = get_associated_token_address(&user.key, &lp_mint.key);
-    //verify that the mint of the ata matches the lp_mint
-    if ata != *lp_ata.key {
-        return Err(FlashLoanError::InvalidAccountData.into());
-    }
-
     burn_tokens(
         lp_ata.clone(),
         lp_mint.clone(),
@@ -142,9 +174,267 @@ pub fn deliquidate_pool(
         lp_amount,
     )?;
 
-    pool_data.total_liquidity = pool_data.total_liquidity
-        .checked_sub(tokens_out)
-        .ok_or(FlashLoanError::MathError)?;
+    let pool_id_bytes = pool_id.to_le_bytes();
+    let signer_seeds = pool_signer_seeds(&pool_id_bytes, &pool_data.bump);
+
+    transfer_from_vault(
+        vault.clone(),
+        token_mint.clone(),
+        user_ata.clone(),
+        pool.clone(), // vault authority (PDA)
+        vault_program.clone(),
+        transfer_hook_accounts,
+        tokens_out,
+        &signer_seeds,
+    )?;
+
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Program moving the pool's asset in and out of the vault, and the transfer hook
+/// accounts. The `token_program` slot holds the LP mint's Token-2022, so pools on another
+/// program (SPL Token, or System for a lamport pool) pass theirs right after the fixed
+/// accounts.
+fn vault_program<'b, 'a>(
+    pool_data: &PoolState,
+    token_program: &'b AccountInfo<'a>,
+    remaining_accounts: &'b [AccountInfo<'a>],
+) -> Result<(&'b AccountInfo<'a>, &'b [AccountInfo<'a>]), ProgramError> {
+    if pool_data.token_program == pool_data.lp_token_program() {
+        return Ok((token_program, remaining_accounts));
+    }
+    let (vault_program, transfer_hook_accounts) = remaining_accounts
+        .split_first()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if vault_program.key != &pool_data.token_program {
+        return Err(FlashLoanError::InvalidTokenProgram.into());
+    }
+    Ok((vault_program, transfer_hook_accounts))
+}
+
+/// The user's side of a deposit or withdrawal: their ATA for the pool's mint, or the
+/// user's own account for a lamport pool.
+fn check_user_token_account(pool_data: &PoolState, user: &AccountInfo, user_ata: &AccountInfo) -> ProgramResult {
+    let expected = if pool_data.is_native() {
+        *user.key
+    } else {
+        get_associated_token_address_with_program_id(user.key, &pool_data.token_mint, &pool_data.token_program)
+    };
+    if user_ata.key != &expected {
+        return Err(FlashLoanError::InvalidAccountData.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::sync,
+        math::shares_for_deposit,
+        pda::{find_lp_mint_address, find_pool_address, find_vault_address},
+        state::{PoolStatus, MINIMUM_LIQUIDITY},
+    };
+    use solana_program::{program_option::COption, program_pack::Pack};
+    use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+    const POOL_ID: u64 = 3;
+
+    /// Key, owner and contents behind one `AccountInfo`.
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            Self { key, owner, lamports: 1, data }
+        }
+
+        fn info(&mut self, is_signer: bool) -> AccountInfo<'_> {
+            AccountInfo::new(&self.key, is_signer, true, &mut self.lamports, &mut self.data, &self.owner, false, 0)
+        }
+    }
+
+    /// An SPL Token pool as InitPool leaves it after the creator's deposit, and one
+    /// depositor. CPIs are no-ops outside the runtime, so the vault's balance is moved
+    /// by hand for every transfer into it.
+    struct PoolFixture {
+        program_id: Pubkey,
+        pool: TestAccount,
+        vault: TestAccount,
+        mint: TestAccount,
+        lp_mint: TestAccount,
+        user: TestAccount,
+        user_ata: TestAccount,
+        lp_ata: TestAccount,
+        lp_token_program: TestAccount,
+        token_program: TestAccount,
+    }
+
+    impl PoolFixture {
+        fn new(initial_amount: u64) -> Self {
+            let program_id = Pubkey::new_unique();
+            let (pool, bump) = find_pool_address(&program_id, POOL_ID);
+            let (vault, vault_bump) = find_vault_address(&program_id, &pool);
+            let (lp_mint, lp_mint_bump) = find_lp_mint_address(&program_id, &pool);
+            let mint = Pubkey::new_unique();
+            let user = Pubkey::new_unique();
+
+            let mut pool_data = PoolState {
+                token_mint: mint,
+                token_program: spl_token::id(),
+                lp_mint,
+                vault,
+                pool_id: POOL_ID,
+                bump,
+                vault_bump,
+                lp_mint_bump,
+                ..Default::default()
+            };
+            pool_data.init_liquidity(initial_amount).unwrap();
+
+            let mut mint_data = vec![0; Mint::LEN];
+            Mint {
+                mint_authority: COption::None,
+                supply: initial_amount,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            }
+            .pack_into_slice(&mut mint_data);
+
+            let user_ata = get_associated_token_address_with_program_id(&user, &mint, &spl_token::id());
+            let lp_ata = get_associated_token_address_with_program_id(&user, &lp_mint, &spl_token_2022::id());
+            let mut fixture = Self {
+                program_id,
+                pool: TestAccount::new(pool, program_id, borsh::to_vec(&pool_data).unwrap()),
+                vault: TestAccount::new(vault, spl_token::id(), vec![0; TokenAccount::LEN]),
+                mint: TestAccount::new(mint, spl_token::id(), mint_data),
+                lp_mint: TestAccount::new(lp_mint, spl_token_2022::id(), Vec::new()),
+                user: TestAccount::new(user, Pubkey::default(), Vec::new()),
+                user_ata: TestAccount::new(user_ata, spl_token::id(), Vec::new()),
+                lp_ata: TestAccount::new(lp_ata, spl_token_2022::id(), Vec::new()),
+                lp_token_program: TestAccount::new(spl_token_2022::id(), Pubkey::default(), Vec::new()),
+                token_program: TestAccount::new(spl_token::id(), Pubkey::default(), Vec::new()),
+            };
+            fixture.set_vault_balance(initial_amount);
+            fixture
+        }
+
+        fn pool_data(&self) -> PoolState {
+            PoolState::try_from_slice(&self.pool.data).unwrap()
+        }
+
+        fn set_status(&mut self, status: PoolStatus) {
+            let pool_data = PoolState { status, ..self.pool_data() };
+            self.pool.data = borsh::to_vec(&pool_data).unwrap();
+        }
+
+        fn vault_balance(&self) -> u64 {
+            TokenAccount::unpack(&self.vault.data).unwrap().amount
+        }
+
+        fn set_vault_balance(&mut self, amount: u64) {
+            TokenAccount {
+                mint: self.mint.key,
+                owner: self.pool.key,
+                amount,
+                state: AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut self.vault.data);
+        }
+
+        /// A plain token transfer into the vault, not booked by the pool.
+        fn donate(&mut self, amount: u64) {
+            self.set_vault_balance(self.vault_balance() + amount);
+        }
+
+        fn sync(&mut self) -> ProgramResult {
+            let accounts = [self.pool.info(false), self.vault.info(false)];
+            sync(&self.program_id, &accounts, POOL_ID)
+        }
+
+        /// Runs LiquidatePool for the user and credits the vault on success. Returns the
+        /// LP tokens minted.
+        fn deposit(&mut self, token_amount: u64, min_lp_out: u64) -> Result<u64, ProgramError> {
+            let lp_supply_before = self.pool_data().total_lp_supply;
+            let accounts = [
+                self.user.info(true),
+                self.user_ata.info(false),
+                self.pool.info(false),
+                self.vault.info(false),
+                self.mint.info(false),
+                self.lp_mint.info(false),
+                self.lp_ata.info(false),
+                self.lp_token_program.info(false),
+                self.token_program.info(false),
+            ];
+            liquidate_pool(&self.program_id, &accounts, POOL_ID, token_amount, min_lp_out, None)?;
+            self.donate(token_amount);
+            Ok(self.pool_data().total_lp_supply - lp_supply_before)
+        }
+    }
+
+    #[test]
+    fn donation_before_a_deposit_is_unprofitable() {
+        // The attacker creates the pool with the smallest deposit, owning a single share
+        let attacker_cost = MINIMUM_LIQUIDITY + 1;
+        let attacker_shares = 1;
+        let mut fixture = PoolFixture::new(attacker_cost);
+
+        // The victim quotes a deposit against the pool as it stands
+        let victim_deposit = 1_000_000;
+        let pool_data = fixture.pool_data();
+        let quote = shares_for_deposit(victim_deposit, pool_data.total_liquidity, pool_data.total_lp_supply).unwrap();
+
+        // The attacker front-runs it by sending tokens straight to the vault and cranking Sync
+        fixture.donate(victim_deposit);
+        fixture.sync().unwrap();
+        assert_eq!(fixture.pool_data().total_donations, victim_deposit);
+
+        // A deposit slipping below its quote is rejected through min_lp_out
+        assert_eq!(fixture.deposit(victim_deposit, quote), Err(FlashLoanError::LpOutputTooLow.into()));
+
+        // and one accepting any price still gets shares
+        let victim_shares = fixture.deposit(victim_deposit, 1).unwrap();
+        assert!(victim_shares > 0);
+
+        // Almost all of the donation went to the dead shares, not the attacker, and the
+        // victim loses less than 0.1% to rounding
+        let mut pool_data = fixture.pool_data();
+        assert!(pool_data.withdraw_liquidity(attacker_shares).unwrap() <= attacker_cost);
+        let victim_out = pool_data.withdraw_liquidity(victim_shares).unwrap();
+        assert!(victim_out >= victim_deposit - victim_deposit / 1_000);
+
+        // Every token in the vault is booked, so a later Sync finds nothing new
+        assert_eq!(fixture.vault_balance(), fixture.pool_data().total_liquidity);
+        fixture.sync().unwrap();
+        assert_eq!(fixture.pool_data().total_donations, victim_deposit);
+    }
+
+    #[test]
+    fn deposits_fail_while_the_pool_is_paused() {
+        for (status, paused) in [
+            (PoolStatus::Active, false),
+            (PoolStatus::BorrowPaused, false),
+            (PoolStatus::DepositPaused, true),
+            (PoolStatus::WithdrawOnly, true),
+        ] {
+            let mut fixture = PoolFixture::new(MINIMUM_LIQUIDITY + 1_000);
+            fixture.set_status(status);
+            let result = fixture.deposit(1_000, 1);
+            if paused {
+                assert_eq!(result, Err(FlashLoanError::PoolPaused.into()), "{status:?}");
+            } else {
+                assert_eq!(result, Ok(1_000), "{status:?}");
+            }
+        }
+    }
+}
@@ -1,11 +1,29 @@
 pub mod init_pool;
 pub mod borrow;
+pub mod borrow_many;
 pub mod repay;
 pub mod liquidate_pool;
 pub mod flash_loan;
+pub mod flash_loan_callback;
+pub mod receiver;
+pub mod update_pool_config;
+pub mod authority;
+pub mod config;
+pub mod collect_protocol_fees;
+pub mod sync;
+pub mod init_native_pool;
 
 pub use init_pool::*;
 pub use borrow::*;
+pub use borrow_many::*;
 pub use repay::*;
 pub use liquidate_pool::*;
-pub use flash_loan::*;
\ No newline at end of file
+pub use flash_loan::*;
+pub use flash_loan_callback::*;
+pub use receiver::*;
+pub use update_pool_config::*;
+pub use authority::*;
+pub use config::*;
+pub use collect_protocol_fees::*;
+pub use sync::*;
+pub use init_native_pool::*;
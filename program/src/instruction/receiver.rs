@@ -0,0 +1,76 @@
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use borsh::{BorshSerialize, BorshDeserialize};
+
+/// Instruction interface a program has to implement to take a callback-style
+/// flash loan through `FlashLoanInstruction::FlashLoan`.
+///
+/// The flash loan program invokes the receiver with these accounts:
+///
+/// 0. `[signer]` borrower, signer privilege forwarded from the outer instruction
+/// 1. `[writable]` borrower token account that now holds the loan, the borrower itself for a lamport pool
+/// 2. `[writable]` pool vault the repayment has to be sent to
+/// 3. `[]` pool token mint
+/// 4. `[]` token program, SPL Token or Token-2022, or the System program for a lamport pool
+/// 5. `..` every extra account passed to `FlashLoan` after its fixed accounts
+///
+/// Before returning, the receiver must move `amount + fee` back into the vault (`fee`
+/// already covers any Token-2022 transfer fee on the way back), usually by a
+/// `transfer_checked` from the borrower token account with the borrower's forwarded
+/// signature, or by a System transfer from the borrower for a lamport pool. The flash
+/// loan program checks the vault balance once the callback returns and fails the whole
+/// instruction if it is short.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum FlashLoanReceiverInstruction {
+    /// 0 - Funds have been lent, run the strategy and repay the vault
+    ReceiveFlashLoan {
+        amount: u64,
+        fee: u64,
+    },
+}
+
+impl FlashLoanReceiverInstruction {
+    /// Unpacks serialized instruction data into an enum variant
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+
+    /// Builds the callback into `receiver_program` with the account layout above.
+    #[allow(clippy::too_many_arguments)]
+    pub fn instruction(
+        &self,
+        receiver_program: &Pubkey,
+        borrower: &Pubkey,
+        borrower_token_account: &Pubkey,
+        vault: &Pubkey,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+        extra_accounts: &[AccountInfo],
+    ) -> Result<Instruction, ProgramError> {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*borrower, true),
+            AccountMeta::new(*borrower_token_account, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*token_program, false),
+        ];
+        accounts.extend(extra_accounts.iter().map(|account| AccountMeta {
+            pubkey: *account.key,
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+        }));
+
+        let data = borsh::to_vec(self).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+        Ok(Instruction {
+            program_id: *receiver_program,
+            accounts,
+            data,
+        })
+    }
+}
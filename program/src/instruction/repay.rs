@@ -7,21 +7,23 @@ use solana_program::{
     rent::Rent,
     system_instruction,
     program::invoke,
-    sysvar::Sysvar,
+    sysvar::{instructions::load_current_index_checked, Sysvar},
 }; 
 use crate::{
-    instruction::FlashLoanInstruction,
-    utils::transfer_tokens,
+    instruction::LoanAccounts,
+    pda::{check_pool_address, check_vault_address},
+    utils::{gross_up_transfer_fee, invoked_via_cpi, transfer_to_vault, vault_balance},
     error::FlashLoanError,
-    state::Pool as PoolState,
+    state::{Pool as PoolState, ProgramConfig},
 };
 
-use borsh::BorshDeserialize;
+use borsh::{BorshSerialize, BorshDeserialize};
 
 pub fn repay(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    pool_id: u8,
+    config: &ProgramConfig,
+    pool_id: u64,
     amount: u64,
 ) -> ProgramResult {
     
@@ -31,17 +33,39 @@ pub fn repay(
     let pool = next_account_info(account_info_iter)?;
     let source = next_account_info(account_info_iter)?;
     let vault = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
-    let clock_sysvar = next_account_info(account_info_iter)?;
-    
-    let clock = solana_program::sysvar::clock::Clock::from_account_info(clock_sysvar)?;
-    let current_time = clock.unix_timestamp as u8;
+    let instructions_sysvar_account = next_account_info(account_info_iter)?;
+    let transfer_hook_accounts = account_info_iter.as_slice();
 
-    //The pool id is the open_time of the pool, so we check if the current time is greater than the pool id
-    if current_time > pool_id {
+    let pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_id != pool_data.pool_id {
         return Err(FlashLoanError::InvalidPoolId.into());
     }
-    if !borrower.is_signer {
+
+    let loan = LoanAccounts {
+        borrower,
+        pool,
+        vault,
+        borrower_token_account: source,
+        mint: token_mint,
+        token_program,
+        transfer_hook_accounts,
+    };
+    let current_ix_index = load_current_index_checked(instructions_sysvar_account)?;
+    close_loan(program_id, &loan, amount, current_ix_index, config)
+}
+
+/// Checks the repayment against the pool's open loan, sends it to the vault and
+/// settles the loan. `ix_index` is the repaying instruction's index in the transaction.
+pub fn close_loan(
+    program_id: &Pubkey,
+    loan: &LoanAccounts,
+    amount: u64,
+    ix_index: u16,
+    config: &ProgramConfig,
+) -> ProgramResult {
+    if !loan.borrower.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
@@ -49,19 +73,132 @@ pub fn repay(
         return Err(FlashLoanError::InvalidAmount.into());
     }
 
-    let pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
-    if pool_id != pool_data.pool_id {
-        return Err(ProgramError::InvalidAccountData);
+    let mut pool_data = PoolState::try_from_slice(&loan.pool.try_borrow_data()?)?;
+    check_pool_address(program_id, loan.pool.key, &pool_data)?;
+    check_vault_address(program_id, loan.pool.key, loan.vault.key, &pool_data)?;
+    pool_data.check_token_accounts(loan.mint.key, loan.token_program.key)?;
+
+    if !pool_data.loan_active {
+        return Err(FlashLoanError::NoActiveLoan.into());
+    }
+
+    if loan.borrower.key != &pool_data.loan_borrower {
+        return Err(FlashLoanError::UnauthorizedAccess.into());
+    }
+
+    // Loans are only opened by top-level instructions, so they are closed by one too
+    if invoked_via_cpi() {
+        return Err(FlashLoanError::LoanModeMismatch.into());
+    }
+
+    // Only a repay after the Borrow that opened the loan may close it
+    if ix_index <= pool_data.loan_ix_index {
+        return Err(FlashLoanError::LoanMustBeRepaidImmediately.into());
+    }
+
+    // The vault only nets what survives the mint's transfer fee
+    if amount < gross_up_transfer_fee(loan.mint, pool_data.loan_amount_owed()?)? {
+        return Err(FlashLoanError::InvalidRepaymentAmount.into());
     }
 
-    transfer_tokens(
-        source.clone(),
-        vault.clone(),
-        borrower.clone(),
-        token_program.clone(),
+    transfer_to_vault(
+        loan.borrower_token_account.clone(),
+        loan.mint.clone(),
+        loan.vault.clone(),
+        loan.borrower.clone(),
+        loan.token_program.clone(),
+        loan.transfer_hook_accounts,
         amount,
-        None,
     )?;
 
+    settle_loan(loan.pool, loan.vault, &mut pool_data, config)
+}
+
+/// Reloads the vault, fails unless it is back at the snapshot plus the fee, splits
+/// the fee between the treasury and the LPs and clears the open loan.
+pub fn settle_loan(
+    pool: &AccountInfo,
+    vault: &AccountInfo,
+    pool_data: &mut PoolState,
+    config: &ProgramConfig,
+) -> ProgramResult {
+    if vault_balance(vault, &pool_data.token_program)? < pool_data.loan_required_vault_balance()? {
+        return Err(FlashLoanError::InvalidRepaymentAmount.into());
+    }
+
+    pool_data.accrue_loan_fee(config.protocol_fee_share_bps)?;
+    pool_data.clear_loan();
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_pack::Pack;
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    /// A pool that lent 1_000 of the 10_000 tokens in its vault for a fee of 9.
+    fn pool_with_open_loan() -> PoolState {
+        PoolState {
+            token_program: spl_token::id(),
+            total_liquidity: 10_000,
+            total_lp_supply: 10_000,
+            loan_borrower: Pubkey::new_unique(),
+            loan_vault_snapshot: 10_000,
+            loan_principal: 1_000,
+            loan_fee: 9,
+            loan_ix_index: 1,
+            loan_active: true,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `settle_loan` with `vault_amount` in the vault. Returns the result and the
+    /// pool as written back to its account.
+    fn settle(pool_data: &mut PoolState, vault_amount: u64) -> (ProgramResult, PoolState) {
+        let (pool_key, vault_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut pool_lamports, mut vault_lamports) = (0, 0);
+        let mut pool_account_data = borsh::to_vec(pool_data).unwrap();
+        let mut vault_data = vec![0; TokenAccount::LEN];
+        TokenAccount { amount: vault_amount, state: AccountState::Initialized, ..Default::default() }
+            .pack_into_slice(&mut vault_data);
+
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let pool = AccountInfo::new(&pool_key, false, true, &mut pool_lamports, &mut pool_account_data, &program_id, false, 0);
+        let vault = AccountInfo::new(&vault_key, false, true, &mut vault_lamports, &mut vault_data, &token_program, false, 0);
+
+        let result = settle_loan(&pool, &vault, pool_data, &ProgramConfig::default());
+        let written = PoolState::try_from_slice(&pool.try_borrow_data().unwrap()).unwrap();
+        (result, written)
+    }
+
+    #[test]
+    fn short_repayment_is_rejected() {
+        let mut pool_data = pool_with_open_loan();
+        let before = pool_data.clone();
+        let (result, written) = settle(&mut pool_data, 10_008);
+
+        assert_eq!(result, Err(FlashLoanError::InvalidRepaymentAmount.into()));
+        assert_eq!(written, before);
+    }
+
+    #[test]
+    fn exact_repayment_clears_the_loan() {
+        let mut pool_data = pool_with_open_loan();
+        let (result, written) = settle(&mut pool_data, 10_009);
+
+        assert_eq!(result, Ok(()));
+        assert!(!written.loan_active);
+        assert_eq!(written.loan_borrower, Pubkey::default());
+        assert_eq!(
+            (written.loan_vault_snapshot, written.loan_principal, written.loan_fee, written.loan_ix_index),
+            (0, 0, 0, 0),
+        );
+        // The whole fee went to the LPs
+        assert_eq!(written.total_liquidity, 10_009);
+    }
+}
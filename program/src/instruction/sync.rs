@@ -0,0 +1,47 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::FlashLoanError,
+    pda::{check_pool_address, check_vault_address},
+    state::Pool as PoolState,
+    utils::vault_balance,
+};
+use borsh::{BorshSerialize, BorshDeserialize};
+
+/// Reconciles the pool's books with its vault. Loan fees are booked when a loan
+/// settles, so anything else found in the vault is a donation and is credited to the
+/// LPs. Anyone may crank it.
+pub fn sync(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool = next_account_info(account_info_iter)?;             // pool state PDA
+    let vault = next_account_info(account_info_iter)?;            // pool vault
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+    check_vault_address(program_id, pool.key, vault.key, &pool_data)?;
+
+    // The vault is short by the principal while a loan is open
+    if pool_data.loan_active {
+        return Err(FlashLoanError::LoanOutstanding.into());
+    }
+
+    pool_data.sync_vault_balance(vault_balance(vault, &pool_data.token_program)?)?;
+
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
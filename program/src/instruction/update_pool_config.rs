@@ -0,0 +1,64 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::FlashLoanError,
+    pda::check_pool_address,
+    state::{Pool as PoolState, PoolStatus},
+};
+use borsh::{BorshSerialize, BorshDeserialize};
+
+pub fn update_pool_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+    fees_bps: Option<u16>,
+    max_loan_amount: Option<u64>,
+    deposit_cap: Option<u64>,
+    status: Option<PoolStatus>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority = next_account_info(account_info_iter)?;   // signer
+    let pool = next_account_info(account_info_iter)?;        // pool state PDA
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_data = PoolState::try_from_slice(&pool.try_borrow_data()?)?;
+    if pool_data.pool_id != pool_id {
+        return Err(FlashLoanError::InvalidPoolId.into());
+    }
+    check_pool_address(program_id, pool.key, &pool_data)?;
+
+    pool_data.check_authority(authority.key)?;
+
+    if pool_data.loan_active {
+        return Err(FlashLoanError::LoanOutstanding.into());
+    }
+
+    // Only the fields that were passed are changed
+    if let Some(fees_bps) = fees_bps {
+        PoolState::validate_fees_bps(fees_bps)?;
+        pool_data.fees_bps = fees_bps;
+    }
+    if let Some(max_loan_amount) = max_loan_amount {
+        pool_data.max_loan_amount = max_loan_amount;
+    }
+    if let Some(deposit_cap) = deposit_cap {
+        pool_data.deposit_cap = deposit_cap;
+    }
+    if let Some(status) = status {
+        pool_data.status = status;
+    }
+
+    pool_data.serialize(&mut &mut pool.try_borrow_mut_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
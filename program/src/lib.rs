@@ -15,12 +15,17 @@ pub mod processor;
 pub mod error;
 pub mod state;
 pub mod utils; 
+pub mod pda;
+pub mod math;
+#[cfg(not(target_os = "solana"))]
+pub mod client;
 
 pub use state::*;
 pub use instruction::*;
 pub use processor::*;
 pub use error::*;
 pub use utils::*;
+pub use pda::*;
 
 entrypoint!(process_instruction);
 
@@ -75,10 +80,8 @@ fn test_init_pool() {
 
     let ix_data = FlashLoanInstruction::InitPool {
         initial_amount: 1_000_000,
-        fees_bps: 50,
-        bump: 255,
+        fees_bps: 5,
         mint: Pubkey::new_unique(),
-        lp_mint_bump: 255,
     }
     .try_to_vec()
     .unwrap();
@@ -103,6 +106,8 @@ fn test_liquidate_pool() {
     let ix_data = FlashLoanInstruction::LiquidatePool {
         pool_id: 0,
         token_amount: 1_000,
+        min_lp_out: 0,
+        deadline: None,
     }
     .try_to_vec()
     .unwrap();
@@ -126,6 +131,8 @@ fn test_de_liquidate_pool() {
     let ix_data = FlashLoanInstruction::DeLiquidatePool {
         pool_id: 0,
         lp_amount: 500,
+        min_tokens_out: 0,
+        deadline: None,
     }
     .try_to_vec()
     .unwrap();
@@ -150,7 +157,6 @@ fn test_borrow() {
         pool_id: 0,
         amount: 1_000,
         mint: Pubkey::new_unique(),
-        bump: 255,
     }
     .try_to_vec()
     .unwrap();
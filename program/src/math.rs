@@ -0,0 +1,107 @@
+use solana_program::program_error::ProgramError;
+
+use crate::error::FlashLoanError;
+
+/// `a * b / denominator` in u128, rounded down.
+pub fn mul_div_floor(a: u64, b: u64, denominator: u64) -> Result<u64, ProgramError> {
+    if denominator == 0 {
+        return Err(FlashLoanError::MathError.into());
+    }
+    let result = (a as u128) * (b as u128) / (denominator as u128);
+    u64::try_from(result).map_err(|_| FlashLoanError::MathError.into())
+}
+
+/// `a * b / denominator` in u128, rounded up.
+pub fn mul_div_ceil(a: u64, b: u64, denominator: u64) -> Result<u64, ProgramError> {
+    if denominator == 0 {
+        return Err(FlashLoanError::MathError.into());
+    }
+    let result = ((a as u128) * (b as u128)).div_ceil(denominator as u128);
+    u64::try_from(result).map_err(|_| FlashLoanError::MathError.into())
+}
+
+/// LP tokens minted for depositing `amount`, rounded down. A pool without LP tokens mints
+/// 1:1. LP tokens backed by no liquidity can't price a deposit, so that fails instead of
+/// diluting their holders.
+pub fn shares_for_deposit(amount: u64, total_liquidity: u64, total_lp_supply: u64) -> Result<u64, ProgramError> {
+    if total_lp_supply == 0 {
+        return Ok(amount);
+    }
+    mul_div_floor(amount, total_lp_supply, total_liquidity)
+}
+
+/// Tokens needed to mint exactly `shares` LP tokens, rounded up. Like `shares_for_deposit`,
+/// fails for LP tokens backed by no liquidity.
+pub fn deposit_for_shares(shares: u64, total_liquidity: u64, total_lp_supply: u64) -> Result<u64, ProgramError> {
+    if total_lp_supply == 0 {
+        return Ok(shares);
+    }
+    if total_liquidity == 0 {
+        return Err(FlashLoanError::MathError.into());
+    }
+    mul_div_ceil(shares, total_liquidity, total_lp_supply)
+}
+
+/// Tokens paid out for burning `shares` LP tokens, rounded down.
+pub fn withdrawal_for_shares(shares: u64, total_liquidity: u64, total_lp_supply: u64) -> Result<u64, ProgramError> {
+    mul_div_floor(shares, total_liquidity, total_lp_supply)
+}
+
+/// LP tokens burned to withdraw exactly `amount`, rounded up.
+pub fn shares_for_withdrawal(amount: u64, total_liquidity: u64, total_lp_supply: u64) -> Result<u64, ProgramError> {
+    mul_div_ceil(amount, total_lp_supply, total_liquidity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rounds_both_ways() {
+        assert_eq!(mul_div_floor(10, 10, 3).unwrap(), 33);
+        assert_eq!(mul_div_ceil(10, 10, 3).unwrap(), 34);
+    }
+
+    #[test]
+    fn mul_div_ceil_keeps_an_exact_quotient() {
+        assert_eq!(mul_div_floor(10, 9, 3).unwrap(), 30);
+        assert_eq!(mul_div_ceil(10, 9, 3).unwrap(), 30);
+        assert_eq!(mul_div_ceil(0, 9, 3).unwrap(), 0);
+    }
+
+    #[test]
+    fn mul_div_does_not_overflow_in_the_product() {
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+        assert!(mul_div_floor(u64::MAX, 2, 1).is_err());
+        assert!(mul_div_floor(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn mul_div_ceil_does_not_overflow_in_the_product() {
+        assert_eq!(mul_div_ceil(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+        // The product of two u64s always fits in u128, only the quotient can be too big
+        assert_eq!(mul_div_ceil(u64::MAX, u64::MAX - 1, u64::MAX).unwrap(), u64::MAX - 1);
+        assert!(mul_div_ceil(u64::MAX, u64::MAX, u64::MAX - 1).is_err());
+        assert!(mul_div_ceil(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn conversions_round_in_the_pools_favour() {
+        // 3 LP tokens backed by 10 tokens
+        assert_eq!(shares_for_deposit(5, 10, 3).unwrap(), 1);
+        assert_eq!(deposit_for_shares(1, 10, 3).unwrap(), 4);
+        assert_eq!(withdrawal_for_shares(1, 10, 3).unwrap(), 3);
+        assert_eq!(shares_for_withdrawal(4, 10, 3).unwrap(), 2);
+    }
+
+    #[test]
+    fn empty_pool_mints_one_to_one() {
+        assert_eq!(shares_for_deposit(1_000, 0, 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn lp_tokens_without_liquidity_reject_deposits() {
+        assert!(shares_for_deposit(1_000, 0, 500).is_err());
+        assert!(deposit_for_shares(1_000, 0, 500).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use solana_program::{
+    entrypoint::ProgramResult,
+    pubkey::Pubkey,
+};
+use std::slice;
+
+use crate::{
+    error::FlashLoanError,
+    state::{Pool, ProgramConfig},
+};
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const LP_MINT_SEED: &[u8] = b"lp_mint";
+
+/// Pool state PDA, `[b"pool", pool_id]`. The pool owns its vault and is the LP mint authority.
+pub fn find_pool_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_SEED, &pool_id.to_le_bytes()], program_id)
+}
+
+/// Vault token account PDA, `[b"vault", pool]`.
+pub fn find_vault_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, pool.as_ref()], program_id)
+}
+
+/// LP mint PDA, `[b"lp_mint", pool]`.
+pub fn find_lp_mint_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LP_MINT_SEED, pool.as_ref()], program_id)
+}
+
+/// Program config PDA, `[b"config"]`.
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ProgramConfig::SEED], program_id)
+}
+
+pub fn pool_signer_seeds<'a>(pool_id: &'a [u8; 8], bump: &'a u8) -> [&'a [u8]; 3] {
+    [POOL_SEED, pool_id, slice::from_ref(bump)]
+}
+
+pub fn vault_signer_seeds<'a>(pool: &'a Pubkey, bump: &'a u8) -> [&'a [u8]; 3] {
+    [VAULT_SEED, pool.as_ref(), slice::from_ref(bump)]
+}
+
+pub fn lp_mint_signer_seeds<'a>(pool: &'a Pubkey, bump: &'a u8) -> [&'a [u8]; 3] {
+    [LP_MINT_SEED, pool.as_ref(), slice::from_ref(bump)]
+}
+
+pub fn config_signer_seeds(bump: &u8) -> [&[u8]; 2] {
+    [ProgramConfig::SEED, slice::from_ref(bump)]
+}
+
+/// Checks `pool` is the PDA of `pool_data`, re-derived from its stored id and bump.
+pub fn check_pool_address(program_id: &Pubkey, pool: &Pubkey, pool_data: &Pool) -> ProgramResult {
+    let pool_id = pool_data.pool_id.to_le_bytes();
+    check_address(program_id, pool, &pool_signer_seeds(&pool_id, &pool_data.bump))
+}
+
+/// Checks `vault` is the pool's vault PDA, re-derived from its stored bump.
+pub fn check_vault_address(program_id: &Pubkey, pool: &Pubkey, vault: &Pubkey, pool_data: &Pool) -> ProgramResult {
+    if vault != &pool_data.vault {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    check_address(program_id, vault, &vault_signer_seeds(pool, &pool_data.vault_bump))
+}
+
+/// Checks `lp_mint` is the pool's LP mint PDA, re-derived from its stored bump.
+pub fn check_lp_mint_address(program_id: &Pubkey, pool: &Pubkey, lp_mint: &Pubkey, pool_data: &Pool) -> ProgramResult {
+    if lp_mint != &pool_data.lp_mint {
+        return Err(FlashLoanError::InvalidPda.into());
+    }
+    check_address(program_id, lp_mint, &lp_mint_signer_seeds(pool, &pool_data.lp_mint_bump))
+}
+
+fn check_address(program_id: &Pubkey, address: &Pubkey, seeds: &[&[u8]]) -> ProgramResult {
+    match Pubkey::create_program_address(seeds, program_id) {
+        Ok(expected) if &expected == address => Ok(()),
+        _ => Err(FlashLoanError::InvalidPda.into()),
+    }
+}
@@ -10,7 +10,14 @@ use solana_program::{
     sysvar::Sysvar,
 }; 
 use crate::{
-    instruction::{FlashLoanInstruction, init_pool, borrow, liquidate_pool, repay, deliquidate_pool}
+    instruction::{
+        FlashLoanInstruction, init_pool, borrow, liquidate_pool, repay, deliquidate_pool,
+        flash_loan, borrow_many, repay_many, update_pool_config,
+        propose_authority, accept_authority, renounce_authority,
+        init_config, set_global_pause, set_protocol_fee_recipient, set_protocol_fee_share,
+        collect_protocol_fees, sync, init_native_pool, check_global_pause,
+    },
+    state::ProgramConfig,
 };
 
 pub fn process(
@@ -18,34 +25,107 @@ pub fn process(
     accounts: &[AccountInfo],
     instruction: FlashLoanInstruction,
 ) -> ProgramResult {
+    // Pool instructions carry the program config first so the global pause is checked once, here,
+    // and its fee settings are handed to the handlers that settle loans
+    let (config_account, config, accounts) = if instruction.is_pausable() {
+        let (config_account, pool_accounts) = accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        (Some(config_account), check_global_pause(program_id, config_account)?, pool_accounts)
+    } else {
+        (None, ProgramConfig::default(), accounts)
+    };
+
     match instruction {
-        // pool_id: u8,
         // initial_amount: u64,
         // fees_bps: u16,
-        // bump: u8,
         // mint: Pubkey,
-        // lp_mint_bump: u8,
         FlashLoanInstruction::InitPool {
             initial_amount,
             fees_bps,
-            bump,
             mint,
-            lp_mint_bump,
-        } => init_pool(program_id, accounts, initial_amount, fees_bps, mint, lp_mint_bump, bump),
+        } => {
+            // The pool id comes from the counter in the config
+            let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            init_pool(program_id, config_account, accounts, initial_amount, fees_bps, mint)
+        }
 
-        FlashLoanInstruction::Borrow { pool_id, amount, mint, bump} => {
-            borrow(program_id, accounts, pool_id, amount, &mint, bump)
+        FlashLoanInstruction::Borrow { pool_id, amount, mint } => {
+            borrow(program_id, accounts, pool_id, amount, &mint)
         }
 
         FlashLoanInstruction::Repay { pool_id, amount } => {
-            repay(program_id, accounts, pool_id, amount)
+            repay(program_id, accounts, &config, pool_id, amount)
+        }
+
+        FlashLoanInstruction::LiquidatePool { pool_id, token_amount, min_lp_out, deadline } => {
+            liquidate_pool(program_id, accounts, pool_id, token_amount, min_lp_out, deadline)
+        }
+        FlashLoanInstruction::DeLiquidatePool { pool_id, lp_amount, min_tokens_out, deadline } => {
+            deliquidate_pool(program_id, accounts, pool_id, lp_amount, min_tokens_out, deadline)
+        }
+
+        FlashLoanInstruction::FlashLoan { pool_id, amount } => {
+            flash_loan(program_id, accounts, &config, pool_id, amount)
+        }
+
+        FlashLoanInstruction::BorrowMany { amounts } => {
+            borrow_many(program_id, accounts, amounts)
+        }
+
+        FlashLoanInstruction::RepayMany { amounts } => {
+            repay_many(program_id, accounts, &config, amounts)
+        }
+
+        FlashLoanInstruction::UpdatePoolConfig {
+            pool_id,
+            fees_bps,
+            max_loan_amount,
+            deposit_cap,
+            status,
+        } => update_pool_config(program_id, accounts, pool_id, fees_bps, max_loan_amount, deposit_cap, status),
+
+        FlashLoanInstruction::ProposeAuthority { pool_id, new_authority } => {
+            propose_authority(program_id, accounts, pool_id, new_authority)
         }
 
-        FlashLoanInstruction::LiquidatePool { pool_id, token_amount } => {
-            liquidate_pool(program_id, accounts, pool_id, token_amount)
+        FlashLoanInstruction::AcceptAuthority { pool_id } => {
+            accept_authority(program_id, accounts, pool_id)
         }
-        FlashLoanInstruction::DeLiquidatePool { pool_id, lp_amount } => {
-            deliquidate_pool(program_id, accounts, pool_id, lp_amount)
+
+        FlashLoanInstruction::RenounceAuthority { pool_id } => {
+            renounce_authority(program_id, accounts, pool_id)
+        }
+
+        FlashLoanInstruction::InitConfig { protocol_fee_recipient } => {
+            init_config(program_id, accounts, protocol_fee_recipient)
+        }
+
+        FlashLoanInstruction::SetGlobalPause { paused } => {
+            set_global_pause(program_id, accounts, paused)
+        }
+
+        FlashLoanInstruction::SetProtocolFeeRecipient { recipient } => {
+            set_protocol_fee_recipient(program_id, accounts, recipient)
+        }
+
+        FlashLoanInstruction::SetProtocolFeeShare { protocol_fee_share_bps } => {
+            set_protocol_fee_share(program_id, accounts, protocol_fee_share_bps)
+        }
+
+        FlashLoanInstruction::CollectProtocolFees { pool_id } => {
+            // The treasury is read from the config, which has to exist
+            let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            collect_protocol_fees(program_id, config_account, accounts, pool_id)
+        }
+
+        FlashLoanInstruction::Sync { pool_id } => {
+            sync(program_id, accounts, pool_id)
+        }
+
+        FlashLoanInstruction::InitNativePool { initial_amount, fees_bps } => {
+            let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            init_native_pool(program_id, config_account, accounts, initial_amount, fees_bps)
         }
     }
 }
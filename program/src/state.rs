@@ -6,33 +6,396 @@ use solana_program::{
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
+    system_program,
     program::invoke,
     sysvar::Sysvar,
 }; 
-use std::mem::size_of;
-use shank::ShankAccount;
+use shank::{ShankAccount, ShankType};
 use borsh::{BorshSerialize, BorshDeserialize, from_slice, to_vec};
 
+use crate::{
+    error::FlashLoanError,
+    math::{mul_div_ceil, shares_for_deposit, withdrawal_for_shares},
+};
+
+/// Highest fee a pool may charge, 1%.
+pub const MAX_FEE_BPS: u16 = 100;
+
+/// Fees a pool can be created with or moved to, so integrators can pick pools by tier.
+pub const FEE_TIERS: [u16; 4] = [1, 5, 9, 30];
+
+/// LP shares locked when a pool is created. They count towards `total_lp_supply` but are
+/// never minted, so the supply can't be brought down to a few shares whose price a
+/// donation could inflate until later deposits round to zero.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Name and symbol of a pool's LP token, derived from the pooled token's symbol when
+/// it has one and from the pool id otherwise.
+pub fn lp_token_metadata(pool_id: u64, underlying_symbol: Option<&str>) -> (String, String) {
+    match underlying_symbol {
+        Some(symbol) if !symbol.is_empty() => (format!("{symbol} Flash Loan LP"), format!("fl{symbol}")),
+        _ => (format!("Flash Loan Pool #{pool_id} LP"), format!("FLP{pool_id}")),
+    }
+}
+
+/// What a pool currently accepts. LPs can withdraw in every status.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ShankType, BorshSerialize, BorshDeserialize)]
+pub enum PoolStatus {
+    #[default]
+    Active,
+    BorrowPaused,
+    DepositPaused,
+    WithdrawOnly,
+}
+
+impl PoolStatus {
+    pub fn can_borrow(&self) -> bool {
+        matches!(self, PoolStatus::Active | PoolStatus::DepositPaused)
+    }
+
+    pub fn can_deposit(&self) -> bool {
+        matches!(self, PoolStatus::Active | PoolStatus::BorrowPaused)
+    }
+}
+
 ///Grouping fixed-size types of similar byte lengths together avoids padding.
 #[repr(C)]
 #[derive(Clone, Debug, Default, PartialEq, ShankAccount, BorshSerialize, BorshDeserialize)]
 pub struct Pool {
     pub token_mint: Pubkey,
-    pub lp_mint: Pubkey,
+    pub token_program: Pubkey,      // SPL Token or Token-2022 owning the mint and vault; System for a lamport pool
+    pub lp_mint: Pubkey,            // always a Token-2022 mint, carrying its own metadata
     
     pub vault: Pubkey,
-    pub pool_id: u8,
+    pub authority: Pubkey,          // Pubkey::default() once renounced
+    pub pending_authority: Pubkey,  // proposed successor, Pubkey::default() if none
+    pub pool_id: u64,
 
     //Liquidity and supply
-    pub total_liquidity: u64,
+    pub total_liquidity: u64,           // tokens backing the LP supply, fees and synced donations included
     pub total_lp_supply: u64,
+    pub accrued_protocol_fees: u64,     // part of the vault owed to the treasury
+    pub total_lp_fees: u64,             // lifetime loan fees credited to LPs
+    pub total_donations: u64,           // lifetime unsolicited deposits picked up by Sync
 
-    pub fees_bps: u8,
+    //Limits set by the authority, 0 means uncapped
+    pub max_loan_amount: u64,
+    pub deposit_cap: u64,
 
-    pub bump: u8,
+    //Open loan, written by Borrow and cleared by Repay
+    pub loan_borrower: Pubkey,
+    pub loan_vault_snapshot: u64,
+    pub loan_principal: u64,
+    pub loan_fee: u64,
+    pub loan_ix_index: u16,
+    pub loan_active: bool,
+
+    pub fees_bps: u16,
+    pub status: PoolStatus,
+
+    pub bump: u8,           // pool PDA bump
+    pub vault_bump: u8,
     pub lp_mint_bump: u8,
 }
 
 impl Pool {
-    pub const LEN: usize = size_of::<Pool>();
+    /// Borsh size, the account is created with exactly this much space.
+    pub const LEN: usize = 32 * 6 + 8       // keys, pool_id
+        + 8 * 5 + 8 * 2                     // liquidity and supply, limits
+        + 32 + 8 * 3 + 2 + 1                // open loan
+        + 2 + 1                             // fees_bps, status
+        + 1 + 1 + 1;                        // bumps
+
+    /// Fails unless `authority` is the pool's current authority.
+    pub fn check_authority(&self, authority: &Pubkey) -> Result<(), ProgramError> {
+        if self.authority == Pubkey::default() {
+            return Err(FlashLoanError::AuthorityRenounced.into());
+        }
+        if *authority != self.authority {
+            return Err(FlashLoanError::InvalidAuthority.into());
+        }
+        Ok(())
+    }
+
+    /// Fails unless `mint` and `token_program` are the pool's.
+    pub fn check_token_accounts(&self, mint: &Pubkey, token_program: &Pubkey) -> Result<(), ProgramError> {
+        if *mint != self.token_mint {
+            return Err(FlashLoanError::InvalidTokenMint.into());
+        }
+        if *token_program != self.token_program {
+            return Err(FlashLoanError::InvalidTokenProgram.into());
+        }
+        Ok(())
+    }
+
+    /// Lamport pools hold SOL directly in a program-owned vault PDA instead of a token account.
+    pub fn is_native(&self) -> bool {
+        self.token_program == system_program::id()
+    }
+
+    /// Program owning the LP mint. LP mints are always Token-2022 so they can carry the
+    /// metadata extension, whatever program the pool's own token runs on.
+    pub fn lp_token_program(&self) -> Pubkey {
+        spl_token_2022::id()
+    }
+
+    /// Fails unless `mint` is the pool's and `token_program` owns its LP mint. Same as
+    /// `check_token_accounts` for Token-2022 pools.
+    pub fn check_lp_token_accounts(&self, mint: &Pubkey, token_program: &Pubkey) -> Result<(), ProgramError> {
+        if *mint != self.token_mint {
+            return Err(FlashLoanError::InvalidTokenMint.into());
+        }
+        if *token_program != self.lp_token_program() {
+            return Err(FlashLoanError::InvalidTokenProgram.into());
+        }
+        Ok(())
+    }
+
+    /// Fails unless `fees_bps` is at most `MAX_FEE_BPS` and one of `FEE_TIERS`.
+    pub fn validate_fees_bps(fees_bps: u16) -> Result<(), ProgramError> {
+        if fees_bps > MAX_FEE_BPS {
+            return Err(FlashLoanError::FeeTooHigh.into());
+        }
+        if !FEE_TIERS.contains(&fees_bps) {
+            return Err(FlashLoanError::InvalidFeeTier.into());
+        }
+        Ok(())
+    }
+
+    /// Books the creator's deposit, locking `MINIMUM_LIQUIDITY` of it as dead shares.
+    /// Returns the LP tokens to mint to the creator.
+    pub fn init_liquidity(&mut self, amount: u64) -> Result<u64, ProgramError> {
+        let creator_shares = match amount.checked_sub(MINIMUM_LIQUIDITY) {
+            Some(shares) if shares > 0 => shares,
+            _ => return Err(FlashLoanError::InitialLiquidityTooLow.into()),
+        };
+        self.total_liquidity = amount;
+        self.total_lp_supply = amount;
+        Ok(creator_shares)
+    }
+
+    /// Books a deposit of `amount`. Returns the LP tokens to mint, rounded down.
+    pub fn deposit_liquidity(&mut self, amount: u64) -> Result<u64, ProgramError> {
+        let shares = shares_for_deposit(amount, self.total_liquidity, self.total_lp_supply)?;
+        if shares == 0 {
+            return Err(FlashLoanError::InvalidAmount.into());
+        }
+        self.total_liquidity = self.total_liquidity
+            .checked_add(amount)
+            .ok_or(FlashLoanError::MathError)?;
+        self.total_lp_supply = self.total_lp_supply
+            .checked_add(shares)
+            .ok_or(FlashLoanError::MathError)?;
+        Ok(shares)
+    }
+
+    /// Books the burn of `shares` LP tokens. Returns the tokens to pay out, rounded down.
+    pub fn withdraw_liquidity(&mut self, shares: u64) -> Result<u64, ProgramError> {
+        let amount = withdrawal_for_shares(shares, self.total_liquidity, self.total_lp_supply)?;
+        if amount == 0 {
+            return Err(FlashLoanError::InvalidAmount.into());
+        }
+        self.total_liquidity = self.total_liquidity
+            .checked_sub(amount)
+            .ok_or(FlashLoanError::MathError)?;
+        self.total_lp_supply = self.total_lp_supply
+            .checked_sub(shares)
+            .ok_or(FlashLoanError::MathError)?;
+        Ok(amount)
+    }
+
+    /// Fee charged on a loan of `amount`, rounded up so small loans aren't free.
+    pub fn calculate_fee(&self, amount: u64) -> Result<u64, ProgramError> {
+        mul_div_ceil(amount, self.fees_bps as u64, 10_000)
+    }
+
+    /// Principal plus fee the borrower has to send back to the vault.
+    pub fn loan_amount_owed(&self) -> Result<u64, ProgramError> {
+        self.loan_principal
+            .checked_add(self.loan_fee)
+            .ok_or(FlashLoanError::MathError.into())
+    }
+
+    /// Drops the open loan once it has been settled.
+    pub fn clear_loan(&mut self) {
+        self.loan_borrower = Pubkey::default();
+        self.loan_vault_snapshot = 0;
+        self.loan_principal = 0;
+        self.loan_fee = 0;
+        self.loan_ix_index = 0;
+        self.loan_active = false;
+    }
+
+    /// Splits the settled loan's fee: the treasury's share is set aside and the rest
+    /// is added to the liquidity backing the LP tokens.
+    pub fn accrue_loan_fee(&mut self, protocol_fee_share_bps: u16) -> Result<(), ProgramError> {
+        let protocol_fee = self.loan_fee
+            .checked_mul(protocol_fee_share_bps as u64)
+            .ok_or(FlashLoanError::MathError)?
+            .checked_div(10_000)
+            .ok_or(FlashLoanError::MathError)?;
+        let lp_fee = self.loan_fee
+            .checked_sub(protocol_fee)
+            .ok_or(FlashLoanError::MathError)?;
+
+        self.accrued_protocol_fees = self.accrued_protocol_fees
+            .checked_add(protocol_fee)
+            .ok_or(FlashLoanError::MathError)?;
+        self.total_liquidity = self.total_liquidity
+            .checked_add(lp_fee)
+            .ok_or(FlashLoanError::MathError)?;
+        self.total_lp_fees = self.total_lp_fees
+            .checked_add(lp_fee)
+            .ok_or(FlashLoanError::MathError)?;
+        Ok(())
+    }
+
+    /// Vault balance already accounted for: LP liquidity plus fees owed to the treasury.
+    pub fn booked_vault_balance(&self) -> Result<u64, ProgramError> {
+        self.total_liquidity
+            .checked_add(self.accrued_protocol_fees)
+            .ok_or(FlashLoanError::MathError.into())
+    }
+
+    /// Credits tokens found in the vault beyond what is booked to the LPs as a donation.
+    /// Returns the amount picked up.
+    pub fn sync_vault_balance(&mut self, vault_balance: u64) -> Result<u64, ProgramError> {
+        let donation = vault_balance.saturating_sub(self.booked_vault_balance()?);
+        self.total_liquidity = self.total_liquidity
+            .checked_add(donation)
+            .ok_or(FlashLoanError::MathError)?;
+        self.total_donations = self.total_donations
+            .checked_add(donation)
+            .ok_or(FlashLoanError::MathError)?;
+        Ok(donation)
+    }
+
+    /// Vault balance Repay has to restore: the pre-loan balance plus the fee.
+    pub fn loan_required_vault_balance(&self) -> Result<u64, ProgramError> {
+        self.loan_vault_snapshot
+            .checked_add(self.loan_fee)
+            .ok_or(FlashLoanError::MathError.into())
+    }
+}
+
+/// Program-wide settings, a single PDA at `[ProgramConfig::SEED]`.
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount, BorshSerialize, BorshDeserialize)]
+pub struct ProgramConfig {
+    pub authority: Pubkey,
+    pub protocol_fee_recipient: Pubkey,
+
+    pub protocol_fee_share_bps: u16,    // share of every loan fee kept for the treasury
+    pub global_paused: bool,
+    pub next_pool_id: u64,              // id handed to the next InitPool, never reused
+    pub bump: u8,
+}
+
+impl ProgramConfig {
+    pub const SEED: &'static [u8] = b"config";
+    pub const LEN: usize = 32 + 32 + 2 + 1 + 8 + 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Books tokens without minting shares, the way a donation to the vault raises the
+    /// share price once it is counted as liquidity.
+    fn donate(pool: &mut Pool, amount: u64) {
+        pool.total_liquidity += amount;
+    }
+
+    #[test]
+    fn init_locks_minimum_liquidity() {
+        let mut pool = Pool::default();
+        assert_eq!(pool.init_liquidity(MINIMUM_LIQUIDITY + 1).unwrap(), 1);
+        assert_eq!(pool.total_lp_supply, MINIMUM_LIQUIDITY + 1);
+
+        assert_eq!(
+            Pool::default().init_liquidity(MINIMUM_LIQUIDITY),
+            Err(FlashLoanError::InitialLiquidityTooLow.into()),
+        );
+    }
+
+    #[test]
+    fn donation_cannot_round_a_deposit_down_to_nothing() {
+        let mut pool = Pool::default();
+        pool.init_liquidity(MINIMUM_LIQUIDITY + 1).unwrap();
+        donate(&mut pool, 1_000_000);
+
+        // Deposits too small to earn a share are rejected instead of being absorbed
+        assert_eq!(pool.deposit_liquidity(999), Err(FlashLoanError::InvalidAmount.into()));
+        assert_eq!(pool.total_liquidity, MINIMUM_LIQUIDITY + 1 + 1_000_000);
+    }
+
+    #[test]
+    fn loan_fee_rounds_up() {
+        let pool = Pool { fees_bps: 9, ..Default::default() };
+        assert_eq!(pool.calculate_fee(10_000).unwrap(), 9);
+        assert_eq!(pool.calculate_fee(10_001).unwrap(), 10);
+        assert_eq!(pool.calculate_fee(1).unwrap(), 1);
+        assert_eq!(pool.calculate_fee(u64::MAX).unwrap(), 16_602_069_666_338_597);
+    }
+
+    #[test]
+    fn repaid_fees_raise_the_share_price() {
+        let mut pool = Pool::default();
+        let shares = pool.init_liquidity(1_000_000).unwrap();
+        pool.loan_fee = 1_000;
+        pool.accrue_loan_fee(2_000).unwrap();
+
+        assert_eq!(pool.accrued_protocol_fees, 200);
+        assert_eq!(pool.total_lp_fees, 800);
+        assert_eq!(pool.withdraw_liquidity(shares).unwrap(), 999_000 + 799);
+    }
+
+    #[test]
+    fn sync_books_only_the_unaccounted_balance_as_donation() {
+        let mut pool = Pool::default();
+        pool.init_liquidity(1_000_000).unwrap();
+        pool.loan_fee = 1_000;
+        pool.accrue_loan_fee(2_000).unwrap();
+
+        // Vault holds the deposit, the settled fee and a 500 token donation
+        assert_eq!(pool.sync_vault_balance(1_001_500).unwrap(), 500);
+        assert_eq!(pool.total_donations, 500);
+        assert_eq!(pool.total_lp_fees, 800);
+        assert_eq!(pool.total_liquidity, 1_001_300);
+
+        // Nothing left to pick up on a second sync
+        assert_eq!(pool.sync_vault_balance(1_001_500).unwrap(), 0);
+    }
+
+    #[test]
+    fn lamport_pool_issues_lp_tokens_with_token_2022() {
+        let pool = Pool {
+            token_mint: spl_token::native_mint::id(),
+            token_program: system_program::id(),
+            ..Default::default()
+        };
+        assert!(pool.is_native());
+        assert!(pool.check_lp_token_accounts(&spl_token::native_mint::id(), &spl_token_2022::id()).is_ok());
+        assert!(pool.check_lp_token_accounts(&spl_token::native_mint::id(), &system_program::id()).is_err());
+        assert!(pool.check_token_accounts(&spl_token::native_mint::id(), &system_program::id()).is_ok());
+
+        let token_pool = Pool {
+            token_program: spl_token::id(),
+            ..Default::default()
+        };
+        assert!(!token_pool.is_native());
+        assert_eq!(token_pool.lp_token_program(), spl_token_2022::id());
+    }
+
+    #[test]
+    fn lp_token_metadata_follows_the_pooled_token() {
+        assert_eq!(
+            lp_token_metadata(7, Some("SOL")),
+            ("SOL Flash Loan LP".to_string(), "flSOL".to_string()),
+        );
+        assert_eq!(
+            lp_token_metadata(7, None),
+            ("Flash Loan Pool #7 LP".to_string(), "FLP7".to_string()),
+        );
+        assert_eq!(lp_token_metadata(7, Some("")), lp_token_metadata(7, None));
+    }
 }
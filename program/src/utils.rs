@@ -3,12 +3,120 @@ use solana_program::{
     program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::rent::Rent,
-    instruction::Instruction,
+    system_instruction,
+    system_program,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+    instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
 };
 
-use spl_token::instruction::{burn, initialize_mint, mint_to, transfer};
+use crate::error::FlashLoanError;
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig, MAX_FEE_BASIS_POINTS},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    extension::metadata_pointer::instruction::initialize as initialize_metadata_pointer,
+    instruction::{burn, initialize_account3, initialize_mint, mint_to},
+    onchain::invoke_transfer_checked,
+    state::{Account as TokenAccount, Mint},
+};
 use spl_associated_token_account::instruction::create_associated_token_account;
+use spl_token_metadata_interface::{instruction::initialize as initialize_token_metadata, state::TokenMetadata};
+
+/// Fails unless `token_program` is the legacy SPL Token program or Token-2022.
+pub fn check_token_program(token_program: &Pubkey) -> Result<(), ProgramError> {
+    if token_program != &spl_token::id() && token_program != &spl_token_2022::id() {
+        return Err(FlashLoanError::InvalidTokenProgram.into());
+    }
+    Ok(())
+}
+
+/// Unpacks a legacy or Token-2022 token account, ignoring its extensions.
+pub fn unpack_token_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    check_token_program(account.owner).map_err(|_| FlashLoanError::InvalidAccountData)?;
+    let data = account.try_borrow_data()?;
+    Ok(StateWithExtensions::<TokenAccount>::unpack(&data)?.base)
+}
+
+/// Unpacks a legacy or Token-2022 mint, ignoring its extensions.
+pub fn unpack_mint(mint: &AccountInfo) -> Result<Mint, ProgramError> {
+    check_token_program(mint.owner).map_err(|_| FlashLoanError::InvalidTokenMint)?;
+    let data = mint.try_borrow_data()?;
+    Ok(StateWithExtensions::<Mint>::unpack(&data)?.base)
+}
+
+/// Space a token account for `mint` needs, including the extensions the mint requires
+/// on its accounts. Legacy mints always need `TokenAccount::LEN`.
+pub fn token_account_len(mint: &AccountInfo) -> Result<usize, ProgramError> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    let account_extensions = ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types()?);
+    ExtensionType::try_calculate_account_len::<TokenAccount>(&account_extensions)
+}
+
+/// Fails if the mint has an extension the pool's accounting can't work with: tokens that
+/// can't be transferred, a permanent delegate that can drain the vault, or a transfer
+/// fee that withholds the whole amount so nothing ever arrives.
+pub fn check_mint_extensions(mint: &AccountInfo) -> Result<(), ProgramError> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    for extension in mint_state.get_extension_types()? {
+        match extension {
+            ExtensionType::NonTransferable | ExtensionType::PermanentDelegate => {
+                return Err(FlashLoanError::UnsupportedMintExtension.into());
+            }
+            ExtensionType::TransferFeeConfig => {
+                let fee_config = mint_state.get_extension::<TransferFeeConfig>()?;
+                for fee in [&fee_config.older_transfer_fee, &fee_config.newer_transfer_fee] {
+                    if u16::from(fee.transfer_fee_basis_points) >= MAX_FEE_BASIS_POINTS {
+                        return Err(FlashLoanError::UnsupportedMintExtension.into());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The mint's transfer fee for the current epoch, `None` without the TransferFee extension.
+fn epoch_transfer_fee(mint: &AccountInfo) -> Result<Option<TransferFee>, ProgramError> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => Ok(Some(*fee_config.get_epoch_fee(Clock::get()?.epoch))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Fee the mint withholds from a transfer of `amount` this epoch, 0 for mints without
+/// the TransferFee extension.
+pub fn transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64, ProgramError> {
+    match epoch_transfer_fee(mint)? {
+        Some(fee) => fee.calculate_fee(amount).ok_or(FlashLoanError::MathError.into()),
+        None => Ok(0),
+    }
+}
+
+/// Amount that has to be sent so that `net_amount` arrives after this epoch's transfer fee.
+pub fn gross_up_transfer_fee(mint: &AccountInfo, net_amount: u64) -> Result<u64, ProgramError> {
+    match epoch_transfer_fee(mint)? {
+        Some(fee) => gross_up(&fee, net_amount),
+        None => Ok(net_amount),
+    }
+}
+
+/// Amount to send so that `net_amount` is left once `fee` is withheld.
+fn gross_up(fee: &TransferFee, net_amount: u64) -> Result<u64, ProgramError> {
+    let gross_amount = fee
+        .calculate_pre_fee_amount(net_amount)
+        .ok_or(FlashLoanError::MathError)?;
+    // A 100% fee reports a pre-fee amount of 0: no transfer can deliver anything
+    if gross_amount < net_amount {
+        return Err(FlashLoanError::UnsupportedMintExtension.into());
+    }
+    Ok(gross_amount)
+}
 
 /// Creates a new mint account (should already be allocated & rent exempt).
 pub fn create_mint<'a>(
@@ -20,8 +128,8 @@ pub fn create_mint<'a>(
     signer_seeds: &[&[&[u8]]],
 ) -> Result<(), ProgramError> {
     let ix = initialize_mint(
-        &token_program.key,
-        &mint.key,
+        token_program.key,
+        mint.key,
         mint_authority,
         None,
         decimals,
@@ -30,21 +138,73 @@ pub fn create_mint<'a>(
     solana_program::program::invoke_signed(&ix, &[mint, rent_sysvar, token_program], signer_seeds)
 }
 
+/// Symbol from a Token-2022 mint's own TokenMetadata extension, `None` if it has none.
+pub fn token_metadata_symbol(mint: &AccountInfo) -> Result<Option<String>, ProgramError> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    Ok(mint_state
+        .get_variable_len_extension::<TokenMetadata>()
+        .ok()
+        .map(|metadata| metadata.symbol))
+}
+
+/// Points an allocated Token-2022 mint's metadata at the mint itself. Has to run
+/// before the mint is initialized.
+pub fn init_metadata_pointer<'a>(
+    mint: AccountInfo<'a>,
+    authority: &Pubkey,
+    token_program: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    let ix = initialize_metadata_pointer(
+        token_program.key,
+        mint.key,
+        Some(*authority),
+        Some(*mint.key),
+    )?;
+
+    invoke(&ix, &[mint, token_program])
+}
+
+/// Writes the TokenMetadata extension into a Token-2022 mint whose metadata pointer is
+/// the mint itself, signed by the mint authority. The mint has to hold enough lamports
+/// for the token program to grow it.
+pub fn init_token_metadata<'a>(
+    mint: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    name: String,
+    symbol: String,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<(), ProgramError> {
+    let ix = initialize_token_metadata(
+        token_program.key,
+        mint.key,
+        authority.key,
+        mint.key,
+        authority.key,
+        name,
+        symbol,
+        String::new(),
+    );
+
+    invoke_signed(&ix, &[mint, authority, token_program], signer_seeds)
+}
+
 /// Creates an associated token account for a wallet + mint.
 pub fn create_ata<'a>(
     payer: AccountInfo<'a>,
-    wallet: &Pubkey,
-    mint: &Pubkey,
+    wallet: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
     ata: AccountInfo<'a>,
     token_program: AccountInfo<'a>,
     ata_program: AccountInfo<'a>,
     system_program: AccountInfo<'a>,
 ) -> Result<(), ProgramError> {
     let ix = create_associated_token_account(
-        &payer.key,
-        wallet,
-        mint,
-        &token_program.key,
+        payer.key,
+        wallet.key,
+        mint.key,
+        token_program.key,
     );
 
     invoke(
@@ -52,6 +212,8 @@ pub fn create_ata<'a>(
         &[
             payer,
             ata,
+            wallet,
+            mint,
             token_program,
             ata_program,
             system_program,
@@ -59,28 +221,172 @@ pub fn create_ata<'a>(
     )
 }
 
-/// Transfers tokens (signed if signer_seeds are provided).
+/// Allocates a program-derived account of `space` bytes owned by `owner`, funded by `payer`.
+/// PDAs are predictable, so anyone can send lamports to one before it is created, which
+/// makes `create_account` fail. Such an account is topped up to rent exemption, then
+/// allocated and assigned instead.
+pub fn create_pda_account<'a>(
+    payer: AccountInfo<'a>,
+    account: AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    rent: &Rent,
+    system_program: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<(), ProgramError> {
+    let required_lamports = rent.minimum_balance(space);
+    if account.lamports() == 0 {
+        let ix = system_instruction::create_account(
+            payer.key,
+            account.key,
+            required_lamports,
+            space as u64,
+            owner,
+        );
+        return invoke_signed(&ix, &[payer, account, system_program], signer_seeds);
+    }
+
+    let top_up = required_lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer, account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        signer_seeds,
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account, system_program],
+        signer_seeds,
+    )
+}
+
+/// Initializes an allocated token account for `mint`, owned by `owner`.
+pub fn init_token_account<'a>(
+    account: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    owner: &Pubkey,
+    token_program: AccountInfo<'a>,
+) -> Result<(), ProgramError> {
+    let ix = initialize_account3(
+        token_program.key,
+        account.key,
+        mint.key,
+        owner,
+    )?;
+
+    invoke(&ix, &[account, mint, token_program])
+}
+
+/// Transfers tokens with `transfer_checked` (signed if signer_seeds are provided).
+/// Works with both the legacy token program and Token-2022. For mints with a
+/// TransferHook extension, the hook program, its ExtraAccountMetaList and the accounts
+/// it lists are picked out of `transfer_hook_accounts` by key and forwarded.
+#[allow(clippy::too_many_arguments)]
 pub fn transfer_tokens<'a>(
     source: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
     destination: AccountInfo<'a>,
     authority: AccountInfo<'a>,
     token_program: AccountInfo<'a>,
+    transfer_hook_accounts: &[AccountInfo<'a>],
     amount: u64,
     signer_seeds: Option<&[&[&[u8]]]> // Some => signed, None => unsigned
 ) -> Result<(), ProgramError> {
-    let ix = transfer(
-        &token_program.key,
-        &source.key,
-        &destination.key,
-        &authority.key,
-        &[],
+    let decimals = unpack_mint(&mint)?.decimals;
+    invoke_transfer_checked(
+        token_program.key,
+        source,
+        mint,
+        destination,
+        authority,
+        transfer_hook_accounts,
         amount,
-    )?;
+        decimals,
+        signer_seeds.unwrap_or(&[]),
+    )
+}
+
+/// Spendable balance of a pool vault: its token amount, or for a lamport vault
+/// (`token_program` is the System program) everything above its rent-exempt reserve.
+pub fn vault_balance(vault: &AccountInfo, token_program: &Pubkey) -> Result<u64, ProgramError> {
+    if token_program == &system_program::id() {
+        let reserve = Rent::get()?.minimum_balance(vault.data_len());
+        return vault
+            .lamports()
+            .checked_sub(reserve)
+            .ok_or(FlashLoanError::MathError.into());
+    }
+    Ok(unpack_token_account(vault)?.amount)
+}
+
+/// Sends `amount` out of a pool vault. Token vaults transfer signed by the pool;
+/// lamport vaults are owned by this program and debited directly.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_from_vault<'a>(
+    vault: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    pool: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    transfer_hook_accounts: &[AccountInfo<'a>],
+    amount: u64,
+    pool_seeds: &[&[u8]],
+) -> Result<(), ProgramError> {
+    if token_program.key == &system_program::id() {
+        let vault_lamports = vault
+            .lamports()
+            .checked_sub(amount)
+            .ok_or(FlashLoanError::InsufficientLiquidity)?;
+        let destination_lamports = destination
+            .lamports()
+            .checked_add(amount)
+            .ok_or(FlashLoanError::MathError)?;
+        **vault.try_borrow_mut_lamports()? = vault_lamports;
+        **destination.try_borrow_mut_lamports()? = destination_lamports;
+        return Ok(());
+    }
+    transfer_tokens(
+        vault,
+        mint,
+        destination,
+        pool,
+        token_program,
+        transfer_hook_accounts,
+        amount,
+        Some(&[pool_seeds]),
+    )
+}
 
-    match signer_seeds {
-        Some(seeds) => invoke_signed(&ix, &[source, destination, authority, token_program], seeds),
-        None => invoke(&ix, &[source, destination, authority, token_program]),
+/// Moves `amount` from `source` into a pool vault. Lamport vaults are funded with a
+/// System transfer, so `source` has to be the signing `authority` itself.
+pub fn transfer_to_vault<'a>(
+    source: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    vault: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    transfer_hook_accounts: &[AccountInfo<'a>],
+    amount: u64,
+) -> Result<(), ProgramError> {
+    if token_program.key == &system_program::id() {
+        let ix = system_instruction::transfer(source.key, vault.key, amount);
+        return invoke(&ix, &[source, vault, token_program]);
     }
+    transfer_tokens(
+        source,
+        mint,
+        vault,
+        authority,
+        token_program,
+        transfer_hook_accounts,
+        amount,
+        None,
+    )
 }
 
 /// Mints tokens to a destination account.
@@ -93,10 +399,10 @@ pub fn mint_tokens<'a>(
     signer_seeds: &[&[&[u8]]],
 ) -> Result<(), ProgramError> {
     let ix = mint_to(
-        &token_program.key,
-        &mint.key,
-        &destination.key,
-        &mint_authority.key,
+        token_program.key,
+        mint.key,
+        destination.key,
+        mint_authority.key,
         &[],
         amount,
     )?;
@@ -116,14 +422,132 @@ pub fn burn_tokens<'a>(
     token_program: AccountInfo<'a>,
     amount: u64,
 ) -> Result<(), ProgramError> {
-    let ix = spl_token::instruction::burn(
-        &token_program.key,
-        &account.key,
-        &mint.key,
-        &authority.key,
+    let ix = burn(
+        token_program.key,
+        account.key,
+        mint.key,
+        authority.key,
         &[],
         amount,
     )?;
 
     invoke(&ix, &[account, mint, authority, token_program])
 }
+
+/// Fails once the cluster clock has passed `deadline`, a unix timestamp. `None` never expires.
+pub fn check_deadline(deadline: Option<i64>) -> Result<(), ProgramError> {
+    if let Some(deadline) = deadline {
+        if Clock::get()?.unix_timestamp > deadline {
+            return Err(FlashLoanError::DeadlineExceeded.into());
+        }
+    }
+    Ok(())
+}
+
+/// True when the running instruction was reached through a CPI instead of
+/// being a top-level instruction of the transaction.
+pub fn invoked_via_cpi() -> bool {
+    is_cpi_stack_height(get_stack_height())
+}
+
+/// True when an instruction running at `height` on the invoke stack is a CPI.
+fn is_cpi_stack_height(height: usize) -> bool {
+    height > TRANSACTION_LEVEL_STACK_HEIGHT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::{
+        non_transferable::NonTransferable, permanent_delegate::PermanentDelegate, StateWithExtensionsMut,
+    };
+
+    fn fee(basis_points: u16, maximum_fee: u64) -> TransferFee {
+        TransferFee {
+            epoch: 0.into(),
+            maximum_fee: maximum_fee.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        }
+    }
+
+    /// Token-2022 mint data carrying `extensions`, set up by `init`.
+    fn mint_data(extensions: &[ExtensionType], init: impl FnOnce(&mut StateWithExtensionsMut<Mint>)) -> Vec<u8> {
+        let mut data = vec![0; ExtensionType::try_calculate_account_len::<Mint>(extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        init(&mut state);
+        state.base = Mint { decimals: 6, is_initialized: true, ..Mint::default() };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn check(mut data: Vec<u8>) -> Result<(), ProgramError> {
+        let (key, owner) = (Pubkey::new_unique(), spl_token_2022::id());
+        let mut lamports = 0;
+        let mint = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        check_mint_extensions(&mint)
+    }
+
+    fn transfer_fee_mint(basis_points: u16) -> Vec<u8> {
+        mint_data(&[ExtensionType::TransferFeeConfig], |state| {
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = fee(basis_points, u64::MAX);
+            config.newer_transfer_fee = fee(basis_points, u64::MAX);
+        })
+    }
+
+    #[test]
+    fn gross_up_covers_the_rounded_up_fee() {
+        let one_percent = fee(100, u64::MAX);
+        // 100 carries a fee of exactly 1, one more token rounds the fee up to 2
+        assert_eq!(gross_up(&one_percent, 99), Ok(100));
+        assert_eq!(gross_up(&one_percent, 100), Ok(102));
+        for net_amount in [1, 99, 100, 101, 9_999, 1_000_000] {
+            let gross_amount = gross_up(&one_percent, net_amount).unwrap();
+            assert_eq!(gross_amount - one_percent.calculate_fee(gross_amount).unwrap(), net_amount);
+        }
+    }
+
+    #[test]
+    fn gross_up_stops_at_the_maximum_fee() {
+        let capped = fee(1_000, 5);
+        assert_eq!(gross_up(&capped, 10), Ok(12));
+        assert_eq!(gross_up(&capped, 1_000), Ok(1_005));
+        assert_eq!(gross_up(&capped, u64::MAX - 5), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn gross_up_rejects_a_full_fee() {
+        assert_eq!(
+            gross_up(&fee(MAX_FEE_BASIS_POINTS, u64::MAX), 1),
+            Err(FlashLoanError::UnsupportedMintExtension.into())
+        );
+    }
+
+    #[test]
+    fn only_nested_instructions_are_cpis() {
+        assert!(!is_cpi_stack_height(TRANSACTION_LEVEL_STACK_HEIGHT));
+        assert!(is_cpi_stack_height(TRANSACTION_LEVEL_STACK_HEIGHT + 1));
+        assert!(is_cpi_stack_height(TRANSACTION_LEVEL_STACK_HEIGHT + 4));
+    }
+
+    #[test]
+    fn plain_and_fee_mints_are_supported() {
+        assert_eq!(check(mint_data(&[], |_| {})), Ok(()));
+        assert_eq!(check(transfer_fee_mint(100)), Ok(()));
+    }
+
+    #[test]
+    fn unsupported_mint_extensions_are_rejected() {
+        let non_transferable = mint_data(&[ExtensionType::NonTransferable], |state| {
+            state.init_extension::<NonTransferable>(true).unwrap();
+        });
+        let permanent_delegate = mint_data(&[ExtensionType::PermanentDelegate], |state| {
+            state.init_extension::<PermanentDelegate>(true).unwrap().delegate =
+                Some(Pubkey::new_unique()).try_into().unwrap();
+        });
+        for data in [non_transferable, permanent_delegate, transfer_fee_mint(MAX_FEE_BASIS_POINTS)] {
+            assert_eq!(check(data), Err(FlashLoanError::UnsupportedMintExtension.into()));
+        }
+    }
+}